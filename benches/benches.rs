@@ -1,5 +1,5 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use qoi::{QoiDecode, QoiEncode};
+use qoi::{QoiDecode, QoiEncode, QoiEncoder};
 
 pub fn four_channels(c: &mut Criterion) {
     let raw = include_bytes!("../images/misc/dice.raw");
@@ -12,11 +12,167 @@ pub fn four_channels(c: &mut Criterion) {
 
     c.bench_function("encode 4 channels", |b| {
         b.iter(|| {
-            raw.qoi_encode_to_vec(header.width(), header.height(), qoi::Channels::Four, 0)
+            raw.qoi_encode_to_vec(
+                header.width(),
+                header.height(),
+                qoi::Channels::Four,
+                qoi::ColourSpace::Srgb,
+            )
+            .unwrap();
+        })
+    });
+}
+
+pub fn mostly_flat(c: &mut Criterion) {
+    // A flat background with a small noisy square in one corner.
+    let mut raw = vec![40; 512 * 512 * 4];
+    for y in 0..32 {
+        for x in 0..32 {
+            let i = (y * 512 + x) * 4;
+            raw[i..i + 4].copy_from_slice(&[(x * 7) as u8, (y * 5) as u8, (x ^ y) as u8, 255]);
+        }
+    }
+
+    c.bench_function("encode mostly flat", |b| {
+        b.iter(|| {
+            raw.qoi_encode_to_vec(512, 512, qoi::Channels::Four, qoi::ColourSpace::Srgb)
+                .unwrap()
+        })
+    });
+}
+
+pub fn noise(c: &mut Criterion) {
+    let mut state = 1u32;
+    let raw: Vec<u8> = (0..512 * 512 * 4)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+
+    for (name, strategy) in [
+        ("encode noise", qoi::EncodeStrategy::Default),
+        ("encode noise without index", qoi::EncodeStrategy::NoIndex),
+    ] {
+        let options = qoi::EncodeOptions {
+            strategy,
+            ..Default::default()
+        };
+        c.bench_function(name, |b| {
+            b.iter(|| {
+                raw.qoi_encode_to_vec_with_options(
+                    512,
+                    512,
+                    qoi::Channels::Four,
+                    qoi::ColourSpace::Srgb,
+                    options,
+                )
+                .unwrap()
+            })
+        });
+    }
+}
+
+pub fn palette(c: &mut Criterion) {
+    // Tiles of 8 colours, so most pixels are found in the index cache.
+    let colours: Vec<[u8; 4]> = (0..8u8)
+        .map(|i| [i * 30, 255 - i * 20, i * 9, 255])
+        .collect();
+    let raw: Vec<u8> = (0..512 * 512)
+        .flat_map(|i| colours[(i / 3 + i / 512 / 5) % colours.len()])
+        .collect();
+
+    for (name, strategy) in [
+        ("encode palette", qoi::EncodeStrategy::Default),
+        ("encode palette biased", qoi::EncodeStrategy::PaletteBiased),
+    ] {
+        let options = qoi::EncodeOptions {
+            strategy,
+            ..Default::default()
+        };
+        let encode = || {
+            raw.qoi_encode_to_vec_with_options(
+                512,
+                512,
+                qoi::Channels::Four,
+                qoi::ColourSpace::Srgb,
+                options,
+            )
+            .unwrap()
+        };
+
+        let ratio = encode().len() as f64 / raw.len() as f64;
+        println!("{name}: {:.2}% of the raw size", ratio * 100.0);
+        c.bench_function(name, |b| b.iter(encode));
+    }
+}
+
+pub fn small_images(c: &mut Criterion) {
+    let raw: Vec<u8> = (0..16 * 16 * 4).map(|i| (i % 7) as u8).collect();
+
+    c.bench_function("encode 16x16 to vec", |b| {
+        b.iter(|| {
+            raw.qoi_encode_to_vec(16, 16, qoi::Channels::Four, qoi::ColourSpace::Srgb)
+                .unwrap()
+        })
+    });
+
+    let mut encoder = QoiEncoder::new();
+    let mut output = Vec::new();
+    c.bench_function("encode 16x16 reusing encoder", |b| {
+        b.iter(|| {
+            encoder
+                .encode_into(
+                    &raw,
+                    16,
+                    16,
+                    qoi::Channels::Four,
+                    qoi::ColourSpace::Srgb,
+                    &mut output,
+                )
                 .unwrap();
         })
     });
 }
 
-criterion_group!(benches, four_channels);
+pub fn channel_conversion(c: &mut Criterion) {
+    let encoded = include_bytes!("../images/misc/dice.qoi");
+    let header = encoded.load_qoi_header().unwrap();
+    let rgb = encoded
+        .qoi_decode_to_vec(Some(qoi::Channels::Three))
+        .unwrap()
+        .qoi_encode_to_vec(
+            header.width(),
+            header.height(),
+            qoi::Channels::Three,
+            qoi::ColourSpace::Srgb,
+        )
+        .unwrap();
+
+    c.bench_function("decode 3 channels", |b| {
+        b.iter(|| rgb.qoi_decode_to_vec(None).unwrap())
+    });
+
+    c.bench_function("decode 3 channels to 4", |b| {
+        b.iter(|| rgb.qoi_decode_to_vec(Some(qoi::Channels::Four)).unwrap())
+    });
+
+    c.bench_function("decode 4 channels to 3", |b| {
+        b.iter(|| {
+            encoded
+                .qoi_decode_to_vec(Some(qoi::Channels::Three))
+                .unwrap()
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    four_channels,
+    mostly_flat,
+    noise,
+    palette,
+    small_images,
+    channel_conversion
+);
 criterion_main!(benches);
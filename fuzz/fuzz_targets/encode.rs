@@ -1,24 +1,26 @@
 #![no_main]
 
 use libfuzzer_sys::fuzz_target;
-use qoi::{Channels, QoiEncode};
-use std::convert::TryInto;
+use qoi::{QoiDecode, QoiEncode, QoiHeader};
 
-fuzz_target!(|data: &[u8]| {
-    if data.len() < 10 {
-        return;
-    }
+fuzz_target!(|input: (QoiHeader, &[u8])| {
+    let (header, raw) = input;
+    let (width, height) = (header.width(), header.height());
+    let channels = header.channels();
+    let colour_space = header.colour_space();
 
-    let width = u32::from_le_bytes((&data[0..4]).try_into().unwrap());
-    let height = u32::from_le_bytes((&data[4..8]).try_into().unwrap());
-
-    let channels = if data[8] < 5 {
-        Channels::Three
-    } else {
-        Channels::Four
+    let encoded = match raw.qoi_encode_to_vec(width, height, channels, colour_space) {
+        Ok(encoded) => encoded,
+        Err(_) => return,
     };
 
-    let colour_space = data[9];
-
-    let _result = (&data[10..]).qoi_encode_to_vec(width, height, channels, colour_space);
+    // Encoding only succeeds when the input covers every pixel, so the
+    // decoded output must match the input truncated to the image size.
+    let raw_image_size = header
+        .raw_image_size(channels)
+        .expect("the size of an encoded image shouldn't overflow");
+    let decoded = encoded
+        .qoi_decode_to_vec(Some(channels))
+        .expect("encoded output should decode");
+    assert_eq!(decoded, &raw[..raw_image_size]);
 });
@@ -0,0 +1,63 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use qoi::{
+    Channels, ColourSpace, DecodeOptions, EncodeOptions, FormatVersion, HashMode, QoiDecode,
+    QoiEncode,
+};
+
+fuzz_target!(|input: (u8, u8, bool, bool, bool, &[u8])| {
+    let (width, height, four, weighted, standard, raw) = input;
+    if width == 0 || height == 0 || raw.is_empty() {
+        return;
+    }
+
+    let (channels, pixel_size) = if four {
+        (Channels::Four, 4)
+    } else {
+        (Channels::Three, 3)
+    };
+    let hash_mode = if weighted {
+        HashMode::Weighted
+    } else {
+        HashMode::Xor
+    };
+    let format_version = if standard {
+        FormatVersion::Standard
+    } else {
+        FormatVersion::Legacy
+    };
+
+    // Repeat the input to cover every pixel, so every input is an image.
+    let size = width as usize * height as usize * pixel_size;
+    let raw: Vec<u8> = raw.iter().copied().cycle().take(size).collect();
+
+    let encoded = raw
+        .qoi_encode_to_vec_with_options(
+            width as u32,
+            height as u32,
+            channels,
+            ColourSpace::Srgb,
+            EncodeOptions {
+                hash_mode,
+                format_version,
+                ..Default::default()
+            },
+        )
+        .expect("an image covering every pixel should encode");
+
+    let mut decoded = vec![0; size];
+    encoded
+        .qoi_decode_with_options(
+            Some(channels),
+            &mut decoded,
+            DecodeOptions {
+                hash_mode,
+                format_version,
+                strict: true,
+                ..Default::default()
+            },
+        )
+        .expect("encoded output should decode");
+    assert_eq!(decoded, raw);
+});
@@ -0,0 +1,43 @@
+use crate::{Channels, ColourSpace, QoiError, QoiHeader};
+
+/// Encoding parameters which suit every frame of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchRecommendation {
+    /// [`Channels::Three`] unless at least one frame uses alpha.
+    pub channels: Channels,
+    /// [`ColourSpace::Srgb`] for opaque batches, otherwise
+    /// [`ColourSpace::SrgbLinearAlpha`].
+    pub colour_space: ColourSpace,
+}
+
+/// Scans the alpha of each 4 channel `frame` once to decide on encoding
+/// parameters shared by the whole batch, so every frame is encoded
+/// consistently.
+pub fn qoi_analyze_batch(
+    frames: &[&[u8]],
+    width: u32,
+    height: u32,
+) -> Result<BatchRecommendation, QoiError> {
+    let raw_image_size = QoiHeader::new(width, height, Channels::Four, ColourSpace::Srgb)
+        .raw_image_size(Channels::Four)?;
+    let mut uses_alpha = false;
+
+    for frame in frames {
+        let frame = frame.get(..raw_image_size).ok_or(QoiError::InputSize)?;
+        uses_alpha = uses_alpha || frame.chunks_exact(4).any(|chunk| chunk[3] != 255);
+    }
+
+    let recommendation = if uses_alpha {
+        BatchRecommendation {
+            channels: Channels::Four,
+            colour_space: ColourSpace::SrgbLinearAlpha,
+        }
+    } else {
+        BatchRecommendation {
+            channels: Channels::Three,
+            colour_space: ColourSpace::Srgb,
+        }
+    };
+
+    Ok(recommendation)
+}
@@ -0,0 +1,31 @@
+use crate::{Channels, ColourSpace, QoiHeader};
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+impl<'a> Arbitrary<'a> for Channels {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        u.choose(&[Channels::Three, Channels::Four]).copied()
+    }
+}
+
+impl<'a> Arbitrary<'a> for ColourSpace {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        u.choose(&[
+            ColourSpace::Srgb,
+            ColourSpace::SrgbLinearAlpha,
+            ColourSpace::Linear,
+        ])
+        .copied()
+    }
+}
+
+impl<'a> Arbitrary<'a> for QoiHeader {
+    /// Generates any dimensions, including those which are too big to encode.
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(QoiHeader::new(
+            u.arbitrary()?,
+            u.arbitrary()?,
+            u.arbitrary()?,
+            u.arbitrary()?,
+        ))
+    }
+}
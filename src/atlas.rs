@@ -0,0 +1,168 @@
+//! A container holding many encoded images, such as the sprites of an atlas.
+//!
+//! The container starts with the magic `qoia` and a big endian `u32` count,
+//! followed by a record for each image of its offset from the start of the
+//! container, its length, width and height, each a big endian `u32`. The
+//! encoded images follow the records.
+
+use crate::QoiError;
+#[cfg(feature = "alloc")]
+use crate::{Channels, ColourSpace, QoiEncode, QoiHeader};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+const MAGIC: &[u8; 4] = b"qoia";
+const PREFIX_SIZE: usize = 8;
+const RECORD_SIZE: usize = 16;
+
+/// Collects encoded images and writes them as an atlas container.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Default)]
+pub struct AtlasWriter {
+    images: Vec<(QoiHeader, Vec<u8>)>,
+}
+
+#[cfg(feature = "alloc")]
+impl AtlasWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an already encoded image, after checking its header.
+    pub fn add_encoded(&mut self, encoded: &[u8]) -> Result<(), QoiError> {
+        let header = QoiHeader::new_from_slice(encoded)?;
+        self.images.push((header, encoded.to_vec()));
+        Ok(())
+    }
+
+    /// Encodes `raw` and adds it.
+    pub fn add_raw(
+        &mut self,
+        raw: &[u8],
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+    ) -> Result<(), QoiError> {
+        let encoded = raw.qoi_encode_to_vec(width, height, channels, colour_space)?;
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        self.images.push((header, encoded));
+        Ok(())
+    }
+
+    /// Writes the container, returning [`QoiError::TooBig`] if an offset or
+    /// length doesn't fit in a `u32`.
+    pub fn finish(self) -> Result<Vec<u8>, QoiError> {
+        let table_size = PREFIX_SIZE + self.images.len() * RECORD_SIZE;
+        let streams_size: usize = self.images.iter().map(|(_, encoded)| encoded.len()).sum();
+        let to_u32 = |value: usize| {
+            u32::try_from(value).map_err(|_| QoiError::TooBig {
+                requested: value,
+                max: u32::MAX as usize,
+            })
+        };
+
+        let mut dest = Vec::with_capacity(table_size + streams_size);
+        dest.extend_from_slice(MAGIC);
+        dest.extend_from_slice(&to_u32(self.images.len())?.to_be_bytes());
+
+        let mut offset = table_size;
+        for (header, encoded) in &self.images {
+            dest.extend_from_slice(&to_u32(offset)?.to_be_bytes());
+            dest.extend_from_slice(&to_u32(encoded.len())?.to_be_bytes());
+            dest.extend_from_slice(&header.width.to_be_bytes());
+            dest.extend_from_slice(&header.height.to_be_bytes());
+            offset += encoded.len();
+        }
+        to_u32(offset)?;
+
+        for (_, encoded) in &self.images {
+            dest.extend_from_slice(encoded);
+        }
+
+        Ok(dest)
+    }
+}
+
+/// Reads the images from an atlas container without copying them.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasReader<'a> {
+    input: &'a [u8],
+    count: usize,
+}
+
+impl<'a> AtlasReader<'a> {
+    /// Checks the magic and that every record is within `input`, returning
+    /// [`QoiError::InputSize`] if one isn't.
+    pub fn new(input: &'a [u8]) -> Result<Self, QoiError> {
+        if input.len() < PREFIX_SIZE {
+            return Err(QoiError::InputSize);
+        }
+
+        if &input[0..4] != MAGIC {
+            return Err(QoiError::IncorrectHeaderMagic);
+        }
+
+        let count = u32::from_be_bytes(input[4..8].try_into().unwrap()) as usize;
+        let table_size = count
+            .checked_mul(RECORD_SIZE)
+            .and_then(|size| size.checked_add(PREFIX_SIZE))
+            .ok_or(QoiError::InputSize)?;
+
+        if input.len() < table_size {
+            return Err(QoiError::InputSize);
+        }
+
+        let reader = Self { input, count };
+        for index in 0..count {
+            let (offset, len) = reader.range(index);
+            if offset.checked_add(len).is_none_or(|end| end > input.len()) {
+                return Err(QoiError::InputSize);
+            }
+        }
+
+        Ok(reader)
+    }
+
+    /// The number of images.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The encoded image at `index`, or `None` if it's out of range.
+    pub fn get(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.count {
+            return None;
+        }
+
+        let (offset, len) = self.range(index);
+        self.input.get(offset..offset + len)
+    }
+
+    /// The width and height recorded for the image at `index`, or `None` if
+    /// it's out of range.
+    pub fn dimensions(&self, index: usize) -> Option<(u32, u32)> {
+        if index >= self.count {
+            return None;
+        }
+
+        let record = self.record(index);
+        Some((record[2], record[3]))
+    }
+
+    fn range(&self, index: usize) -> (usize, usize) {
+        let record = self.record(index);
+        (record[0] as usize, record[1] as usize)
+    }
+
+    fn record(&self, index: usize) -> [u32; 4] {
+        let start = PREFIX_SIZE + index * RECORD_SIZE;
+        let bytes = &self.input[start..start + RECORD_SIZE];
+        let field = |i: usize| u32::from_be_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap());
+        [field(0), field(1), field(2), field(3)]
+    }
+}
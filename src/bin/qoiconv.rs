@@ -0,0 +1,79 @@
+//! Converts between PNG and QOI images, choosing the direction from the file
+//! extensions:
+//!
+//! ```text
+//! qoiconv input.png output.qoi
+//! qoiconv input.qoi output.png
+//! ```
+
+use image::DynamicImage;
+use qoi::{EncodedImage, QoiDecode};
+use std::{error::Error, path::Path, process::ExitCode};
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+/// Converts `input` to `output`, returning the size of the raw pixels.
+fn convert(input: &Path, output: &Path) -> Result<usize, Box<dyn Error>> {
+    match (extension(input).as_deref(), extension(output).as_deref()) {
+        (Some("png"), Some("qoi")) => {
+            let image = image::open(input)?;
+            // QOI only holds 8 bit RGB or RGBA pixels.
+            let image = if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            };
+
+            let encoded = EncodedImage::try_from(&image)?;
+            std::fs::write(output, encoded)?;
+            Ok(image.as_bytes().len())
+        }
+        (Some("qoi"), Some("png")) => {
+            let image = std::fs::read(input)?.decode_to_dynamic_image()?;
+            image.save(output)?;
+            Ok(image.as_bytes().len())
+        }
+        _ => Err("expected a .png input and .qoi output, or the reverse".into()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, input, output] = &args[..] else {
+        eprintln!("usage: qoiconv <input> <output>");
+        return ExitCode::FAILURE;
+    };
+    let (input, output) = (Path::new(input), Path::new(output));
+
+    let raw_size = match convert(input, output) {
+        Ok(raw_size) => raw_size,
+        Err(error) => {
+            eprintln!("qoiconv: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let size = |path: &Path| std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let qoi_size = if extension(input).as_deref() == Some("qoi") {
+        size(input)
+    } else {
+        size(output)
+    };
+
+    println!(
+        "{} ({} bytes) -> {} ({} bytes)",
+        input.display(),
+        size(input),
+        output.display(),
+        size(output)
+    );
+    println!(
+        "The QOI image is {:.1}% of the {} byte raw image",
+        qoi_size as f64 * 100.0 / raw_size as f64,
+        raw_size
+    );
+
+    ExitCode::SUCCESS
+}
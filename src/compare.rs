@@ -0,0 +1,23 @@
+use crate::Channels;
+
+/// Compares two decoded images with `width` pixels per row, returning the
+/// coordinates of the first pixel which differs, or `None` if they match.
+///
+/// If one image is longer, the first pixel past the end of the other one is
+/// reported. A `width` of zero is treated as one.
+pub fn compare_decoded(a: &[u8], b: &[u8], width: u32, channels: Channels) -> Option<(u32, u32)> {
+    let pixel_size = channels.len() as usize;
+    let width = width.max(1) as usize;
+
+    let pixels = a.len().max(b.len()).div_ceil(pixel_size);
+    let i = (0..pixels).find(|&i| pixel(a, i, pixel_size) != pixel(b, i, pixel_size))?;
+
+    Some(((i % width) as u32, (i / width) as u32))
+}
+
+/// The bytes of pixel `i`, which may be partial or empty past the end.
+fn pixel(image: &[u8], i: usize, pixel_size: usize) -> &[u8] {
+    let start = (i * pixel_size).min(image.len());
+    let end = (start + pixel_size).min(image.len());
+    &image[start..end]
+}
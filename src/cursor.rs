@@ -0,0 +1,134 @@
+use crate::{decode::PixelDecoder, Pixel, Qoi, QoiError, QoiHeader};
+
+/// A decoded opcode and its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    /// The pixel at this position in the index cache.
+    Index(u8),
+    /// The previous pixel repeated this many times, from 1 to 0x2020.
+    Run(u16),
+    Diff8 {
+        dr: i8,
+        dg: i8,
+        db: i8,
+    },
+    Diff16 {
+        dr: i8,
+        dg: i8,
+        db: i8,
+    },
+    Diff24 {
+        dr: i8,
+        dg: i8,
+        db: i8,
+        da: i8,
+    },
+    /// The channels which are replaced, the others are unchanged.
+    Color {
+        r: Option<u8>,
+        g: Option<u8>,
+        b: Option<u8>,
+        a: Option<u8>,
+    },
+}
+
+impl Opcode {
+    /// Describes the complete opcode in `bytes`, which has already been
+    /// validated by decoding it.
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let b1 = bytes[0];
+
+        if b1 & Qoi::MASK_2 == Qoi::INDEX {
+            Self::Index(b1 ^ Qoi::INDEX)
+        } else if b1 & Qoi::MASK_3 == Qoi::RUN_8 {
+            Self::Run((b1 & 0x1f) as u16 + 1)
+        } else if b1 & Qoi::MASK_3 == Qoi::RUN_16 {
+            Self::Run(((((b1 & 0x1f) as u16) << 8) | bytes[1] as u16) + 33)
+        } else if b1 & Qoi::MASK_2 == Qoi::DIFF_8 {
+            Self::Diff8 {
+                dr: ((b1 >> 4) & 0x03) as i8 - 2,
+                dg: ((b1 >> 2) & 0x03) as i8 - 2,
+                db: (b1 & 0x03) as i8 - 2,
+            }
+        } else if b1 & Qoi::MASK_3 == Qoi::DIFF_16 {
+            Self::Diff16 {
+                dr: (b1 & 0x1f) as i8 - 16,
+                dg: (bytes[1] >> 4) as i8 - 8,
+                db: (bytes[1] & 0x0f) as i8 - 8,
+            }
+        } else if b1 & Qoi::MASK_4 == Qoi::DIFF_24 {
+            let (b2, b3) = (bytes[1], bytes[2]);
+            Self::Diff24 {
+                dr: (((b1 & 0x0f) << 1) | (b2 >> 7)) as i8 - 16,
+                dg: ((b2 & 0x7c) >> 2) as i8 - 16,
+                db: (((b2 & 0x03) << 3) | ((b3 & 0xe0) >> 5)) as i8 - 16,
+                da: (b3 & 0x1f) as i8 - 16,
+            }
+        } else {
+            let mut operands = bytes[1..].iter().copied();
+            let mut channel = |bit: u8| (b1 & bit > 0).then(|| operands.next()).flatten();
+
+            Self::Color {
+                r: channel(8),
+                g: channel(4),
+                b: channel(2),
+                a: channel(1),
+            }
+        }
+    }
+
+    /// The number of pixels the opcode produces.
+    pub fn pixels(&self) -> usize {
+        match self {
+            Self::Run(run) => *run as usize,
+            _ => 1,
+        }
+    }
+}
+
+/// Steps through an encoded image one opcode at a time, for inspecting the
+/// stream.
+pub struct OpcodeCursor<'a> {
+    decoder: PixelDecoder<'a>,
+    header: QoiHeader,
+    remaining: usize,
+}
+
+impl<'a> OpcodeCursor<'a> {
+    pub fn new(input: &'a [u8]) -> Result<Self, QoiError> {
+        let (header, decoder) = PixelDecoder::new(input)?;
+        let remaining = (header.width as usize).saturating_mul(header.height as usize);
+
+        Ok(Self {
+            decoder,
+            header,
+            remaining,
+        })
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+
+    /// The offset of the next opcode in the encoded image.
+    pub fn pos(&self) -> usize {
+        self.decoder.pos()
+    }
+
+    /// Decodes the next opcode, returning it and the resulting pixel. Returns
+    /// `None` once every pixel has been decoded or the padding is reached.
+    pub fn next_op(&mut self) -> Result<Option<(Opcode, Pixel)>, QoiError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        let Some(bytes) = self.decoder.next_opcode()? else {
+            return Ok(None);
+        };
+
+        let opcode = Opcode::from_bytes(bytes);
+        self.remaining = self.remaining.saturating_sub(opcode.pixels());
+
+        Ok(Some((opcode, self.decoder.pixel())))
+    }
+}
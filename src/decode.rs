@@ -1,13 +1,714 @@
-use crate::{Channels, FallibleReader, Pixel, Qoi, QoiError, QoiHeader};
+#[cfg(feature = "instrument")]
+use crate::Metrics;
+use crate::{
+    hash::Fnv1a, output_prefix, ChannelOrder, Channels, FallibleReader, FormatVersion, HashMode,
+    OpcodeCursor, Pixel, Qoi, QoiError, QoiHeader, QoiPixels, QoiRuns, QoiStats, Source,
+};
+#[cfg(feature = "alloc")]
+use crate::{DecodedImage, QoiRingDecoder, QoiRows};
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io::{ErrorKind, Read, Write};
+
+/// The decoding state machine, applying one opcode at a time.
+pub(crate) struct DecodeState {
+    cache: [Pixel; 64],
+    pixel: Pixel,
+    run: u16,
+    hash_mode: HashMode,
+}
+
+impl DecodeState {
+    pub(crate) fn new(hash_mode: HashMode) -> Self {
+        Self {
+            cache: [Pixel::default(); 64],
+            pixel: Pixel::new(0, 0, 0, 255),
+            run: 0,
+            hash_mode,
+        }
+    }
+
+    /// Reads one opcode and applies it to the current pixel.
+    #[inline(always)]
+    pub(crate) fn read_op(&mut self, reader: &mut impl Source) -> Result<(), QoiError> {
+        let pos = reader.pos();
+        let pixel = &mut self.pixel;
+        let b1 = reader.read()?;
+
+        if b1 & Qoi::MASK_2 == Qoi::INDEX {
+            *pixel = self.cache[(b1 ^ Qoi::INDEX) as usize];
+        } else if b1 & Qoi::MASK_3 == Qoi::RUN_8 {
+            self.run = (b1 & 0x1f) as u16;
+        } else if b1 & Qoi::MASK_3 == Qoi::RUN_16 {
+            let b2 = reader.read()?;
+            self.run = ((((b1 & 0x1f) as u16) << 8) | b2 as u16) + 32;
+        } else if (b1 & Qoi::MASK_2) == Qoi::DIFF_8 {
+            pixel.modify_r(((b1 >> 4) & 0x03) as i8 - 2);
+            pixel.modify_g(((b1 >> 2) & 0x03) as i8 - 2);
+            pixel.modify_b((b1 & 0x03) as i8 - 2);
+        } else if (b1 & Qoi::MASK_3) == Qoi::DIFF_16 {
+            let b2 = reader.read()?;
+            pixel.modify_r((b1 & 0x1f) as i8 - 16);
+            pixel.modify_g((b2 >> 4) as i8 - 8);
+            pixel.modify_b((b2 & 0x0f) as i8 - 8);
+        } else if (b1 & Qoi::MASK_4) == Qoi::DIFF_24 {
+            let b2 = reader.read()?;
+            let b3 = reader.read()?;
+
+            pixel.modify_r((((b1 & 0x0f) << 1) | (b2 >> 7)) as i8 - 16);
+            pixel.modify_g(((b2 & 0x7c) >> 2) as i8 - 16);
+            pixel.modify_b((((b2 & 0x03) << 3) | ((b3 & 0xe0) >> 5)) as i8 - 16);
+            // Alpha is tracked even when decoding to 3 channels, as it's part
+            // of the cache index and later INDEX opcodes depend on it.
+            pixel.modify_a((b3 & 0x1f) as i8 - 16);
+        } else if (b1 & Qoi::MASK_4) == Qoi::COLOR {
+            if b1 & 8 > 0 {
+                pixel.r = reader.read()?;
+            }
+
+            if b1 & 4 > 0 {
+                pixel.g = reader.read()?;
+            }
+
+            if b1 & 2 > 0 {
+                pixel.b = reader.read()?;
+            }
+
+            if b1 & 1 > 0 {
+                pixel.a = reader.read()?;
+            }
+        }
+
+        *(self
+            .cache
+            .get_mut(self.hash_mode.cache_index(pixel))
+            .ok_or(QoiError::CacheIndex { pos })?) = *pixel;
+
+        Ok(())
+    }
+
+    /// Decodes the next pixel, reading an opcode only if there's no pending
+    /// run.
+    #[cfg(feature = "std")]
+    #[inline(always)]
+    pub(crate) fn next_pixel(&mut self, reader: &mut impl Source) -> Result<Pixel, QoiError> {
+        if self.run > 0 {
+            self.run -= 1;
+        } else {
+            self.read_op(reader)?;
+        }
+
+        Ok(self.pixel)
+    }
+}
+
+/// Decodes the pixels of an encoded slice one at a time.
+pub(crate) struct PixelDecoder<'a> {
+    reader: FallibleReader<'a>,
+    padding_pos: usize,
+    end_marker: &'static [u8],
+    /// Whether the end marker will be checked, see [`PixelDecoder::unexpected_end`].
+    require_marker: bool,
+    state: DecodeState,
+}
+
+impl<'a> PixelDecoder<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Result<(QoiHeader, Self), QoiError> {
+        Self::with_options(input, &DecodeOptions::default())
+    }
+
+    /// Creates a decoder using the hash mode and format version from
+    /// `options`. The end marker of [`FormatVersion::Standard`] images is
+    /// always checked.
+    pub(crate) fn with_options(
+        input: &'a [u8],
+        options: &DecodeOptions,
+    ) -> Result<(QoiHeader, Self), QoiError> {
+        let header = QoiHeader::new_from_slice(input)?;
+        let mut reader = FallibleReader::new(input);
+        reader.read_slice(Qoi::HEADER_SIZE)?;
+
+        let end_marker = options.format_version.end_marker();
+        let padding_pos = input.len().saturating_sub(end_marker.len());
+
+        if options.format_version == FormatVersion::Standard && padding_pos < Qoi::HEADER_SIZE {
+            return Err(QoiError::InvalidPadding);
+        }
+
+        let decoder = Self {
+            reader,
+            padding_pos,
+            end_marker,
+            require_marker: options.strict || options.format_version == FormatVersion::Standard,
+            state: DecodeState::new(options.hash_mode),
+        };
+
+        Ok((header, decoder))
+    }
+
+    /// Reads one opcode, returning [`QoiError::UnexpectedEnd`] if it extends
+    /// into the padding.
+    #[inline(always)]
+    fn read_op(&mut self) -> Result<(), QoiError> {
+        let pos = self.reader.pos;
+        self.state.read_op(&mut self.reader)?;
+
+        if self.reader.pos > self.padding_pos {
+            return Err(self.unexpected_end(pos));
+        }
+
+        Ok(())
+    }
+
+    /// The error for an opcode at `pos` extending into the last bytes of the
+    /// input. If the end marker is being checked and those bytes aren't one,
+    /// then there's no room left for it, so [`QoiError::InvalidPadding`] is
+    /// returned.
+    #[cold]
+    fn unexpected_end(&self, pos: usize) -> QoiError {
+        if self.require_marker && !self.reader.buf.ends_with(self.end_marker) {
+            return QoiError::InvalidPadding;
+        }
+
+        QoiError::UnexpectedEnd { pos }
+    }
+
+    /// Decodes the next pixel. Once the padding is reached the last pixel is
+    /// repeated.
+    #[inline(always)]
+    pub(crate) fn next_pixel(&mut self) -> Result<Pixel, QoiError> {
+        if self.state.run > 0 {
+            self.state.run -= 1;
+        } else if self.reader.pos < self.padding_pos {
+            self.read_op()?;
+        }
+
+        Ok(self.state.pixel)
+    }
+
+    /// Decodes the next pixel, returning [`QoiError::UnexpectedEnd`] if the
+    /// padding has been reached.
+    #[inline(always)]
+    pub(crate) fn next_pixel_strict(&mut self) -> Result<Pixel, QoiError> {
+        if self.state.run > 0 {
+            self.state.run -= 1;
+        } else if self.reader.pos < self.padding_pos {
+            self.read_op()?;
+        } else {
+            return Err(self.unexpected_end(self.reader.pos));
+        }
+
+        Ok(self.state.pixel)
+    }
+
+    /// Decodes the next opcode, returning its pixel and the number of times
+    /// it's repeated including any run. Once the padding is reached the last
+    /// pixel is returned with a count of `usize::MAX`.
+    pub(crate) fn next_run(&mut self) -> Result<(Pixel, usize), QoiError> {
+        let count = if self.state.run > 0 {
+            self.state.run as usize
+        } else if self.reader.pos < self.padding_pos {
+            self.read_op()?;
+            1 + self.state.run as usize
+        } else {
+            return Ok((self.state.pixel, usize::MAX));
+        };
+
+        self.state.run = 0;
+        Ok((self.state.pixel, count))
+    }
+
+    /// Reads one opcode, discarding any run it starts, and returns its bytes.
+    /// Returns `None` once the padding is reached.
+    pub(crate) fn next_opcode(&mut self) -> Result<Option<&'a [u8]>, QoiError> {
+        let pos = self.reader.pos;
+        if pos >= self.padding_pos {
+            return Ok(None);
+        }
+
+        self.read_op()?;
+        self.state.run = 0;
+
+        Ok(Some(&self.reader.buf[pos..self.reader.pos]))
+    }
+
+    /// The current pixel.
+    pub(crate) fn pixel(&self) -> Pixel {
+        self.state.pixel
+    }
+
+    /// The offset of the next opcode.
+    pub(crate) fn pos(&self) -> usize {
+        self.reader.pos
+    }
+
+    /// The number of input bytes consumed so far, including the header and
+    /// the padding which follows the opcodes.
+    pub(crate) fn consumed(&self) -> usize {
+        (self.reader.pos + self.end_marker.len()).min(self.reader.buf.len())
+    }
+
+    /// Checks that the end marker follows the current position. Any bytes
+    /// after it are ignored.
+    pub(crate) fn check_padding(&self) -> Result<(), QoiError> {
+        let pos = self.reader.pos;
+        if self.reader.buf.get(pos..pos + self.end_marker.len()) != Some(self.end_marker) {
+            return Err(QoiError::InvalidPadding);
+        }
+
+        Ok(())
+    }
+
+    /// Skips `count` pixels, consuming pending runs without iterating over
+    /// them.
+    #[inline]
+    pub(crate) fn skip(&mut self, mut count: usize) -> Result<(), QoiError> {
+        while count > 0 {
+            if self.state.run > 0 {
+                let skipped = (self.state.run as usize).min(count);
+                self.state.run -= skipped as u16;
+                count -= skipped;
+            } else {
+                self.next_pixel()?;
+                count -= 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes `count` pixels without producing them, checking that the
+    /// padding follows the last opcode. Like decoding, any bytes after the
+    /// padding are ignored.
+    pub(crate) fn validate(&mut self, mut count: usize) -> Result<(), QoiError> {
+        while count > 0 {
+            if self.state.run > 0 {
+                let skipped = (self.state.run as usize).min(count);
+                self.state.run -= skipped as u16;
+                count -= skipped;
+            } else {
+                self.next_pixel_strict()?;
+                count -= 1;
+            }
+        }
+
+        if self.state.run > 0 {
+            return Err(QoiError::InputSize);
+        }
+
+        // Without the padding here, input ending in it means there are extra
+        // opcodes before it.
+        let padding = self.check_padding();
+        if padding.is_err()
+            && self.reader.pos < self.padding_pos
+            && self.reader.buf.ends_with(self.end_marker)
+        {
+            return Err(QoiError::InputSize);
+        }
+
+        padding
+    }
+}
+
+/// Decodes the next pixel for an `N` channel output, applying the checks and
+/// conversions from `options`.
+#[inline(always)]
+fn next_output_pixel<const N: usize>(
+    decoder: &mut PixelDecoder,
+    options: &DecodeOptions,
+) -> Result<Pixel, QoiError> {
+    let pixel = if options.strict {
+        decoder.next_pixel_strict()?
+    } else {
+        decoder.next_pixel()?
+    };
+
+    if options.deny_lossy_conversion && N == 3 && pixel.a != 255 {
+        return Err(QoiError::LossyChannelConversion);
+    }
+
+    if options.premultiply_alpha && N == 4 {
+        return Ok(pixel.premultiplied());
+    }
+
+    Ok(pixel)
+}
+
+/// Decodes pixels into `dest` until it's full, with the number of channels
+/// fixed at compile time so writing each pixel doesn't branch on it.
+#[inline(always)]
+fn decode_pixels<const N: usize>(
+    decoder: &mut PixelDecoder,
+    dest: &mut [u8],
+    options: &DecodeOptions,
+) -> Result<(), QoiError> {
+    for chunk in dest.chunks_exact_mut(N) {
+        let pixel = next_output_pixel::<N>(decoder, options)?;
+        chunk.copy_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a][..N]);
+    }
+
+    Ok(())
+}
+
+/// Decodes the whole image into `dest`, writing each pixel at its mirrored
+/// position according to `options.transform`.
+fn decode_transformed<const N: usize>(
+    decoder: &mut PixelDecoder,
+    header: &QoiHeader,
+    dest: &mut [u8],
+    options: &DecodeOptions,
+) -> Result<(), QoiError> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let row_size = width * N;
+    let dest = output_prefix(dest, header.raw_image_size(Channels::try_from(N as u8)?)?)?;
+    let transform = options.transform;
+
+    for y in 0..height {
+        let dest_y = if transform.flip_v { height - 1 - y } else { y };
+        let row = &mut dest[dest_y * row_size..(dest_y + 1) * row_size];
+
+        for x in 0..width {
+            let dest_x = if transform.flip_h { width - 1 - x } else { x };
+            let pixel = next_output_pixel::<N>(decoder, options)?;
+            row[dest_x * N..(dest_x + 1) * N]
+                .copy_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a][..N]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns [`QoiError::OutputTooSmall`] if `chunk` can't hold a pixel with
+/// `channels`.
+#[inline(always)]
+fn check_chunk(chunk: &[u8], channels: Channels) -> Result<(), QoiError> {
+    if chunk.len() < channels.len() as usize {
+        return Err(QoiError::OutputTooSmall {
+            needed: channels.len() as usize,
+            got: chunk.len(),
+        });
+    }
+
+    Ok(())
+}
+
+#[inline(always)]
+pub(crate) fn write_pixel(
+    chunk: &mut [u8],
+    pixel: Pixel,
+    channels: Channels,
+) -> Result<(), QoiError> {
+    check_chunk(chunk, channels)?;
+    chunk[0] = pixel.r;
+    chunk[1] = pixel.g;
+    chunk[2] = pixel.b;
+
+    if channels.len() == 4 {
+        chunk[3] = pixel.a;
+    }
+
+    Ok(())
+}
+
+#[inline(always)]
+fn write_pixel_ordered(
+    chunk: &mut [u8],
+    pixel: Pixel,
+    channels: Channels,
+    order: ChannelOrder,
+) -> Result<(), QoiError> {
+    let [r, g, b, a] = order.positions(channels);
+    check_chunk(chunk, channels)?;
+    chunk[r] = pixel.r;
+    chunk[g] = pixel.g;
+    chunk[b] = pixel.b;
+
+    if channels.len() == 4 {
+        chunk[a] = pixel.a;
+    }
+
+    Ok(())
+}
 
 pub trait QoiDecode {
+    /// Decodes the image into `dest` using `channels`, or the header's
+    /// channels if `None`, regardless of how many channels were encoded:
+    ///
+    /// | Encoded | Decoded | Conversion |
+    /// |---------|---------|------------|
+    /// | 3 | 3 | None |
+    /// | 3 | 4 | Alpha is 255 |
+    /// | 4 | 3 | Alpha is discarded, see [`DecodeOptions::deny_lossy_conversion`] |
+    /// | 4 | 4 | None |
+    ///
+    /// Returns [`QoiError::OutputTooSmall`] if `dest` is shorter than
+    /// `width * height * channels` bytes, and leaves any bytes after that
+    /// unchanged. Any `&mut [u8]` works as `dest`, including one borrowed
+    /// from a memory mapped file.
     fn qoi_decode(
         &self,
         channels: Option<Channels>,
         dest: impl AsMut<[u8]>,
     ) -> Result<(), QoiError>;
+
+    /// Decodes an image from [`QoiEncode::qoi_encode_with_cache`], starting
+    /// from `cache` and `previous` and updating them for the next image.
+    ///
+    /// [`QoiEncode::qoi_encode_with_cache`]: crate::QoiEncode::qoi_encode_with_cache
+    fn qoi_decode_with_cache(
+        &self,
+        channels: Option<Channels>,
+        cache: &mut [Pixel; 64],
+        previous: &mut Pixel,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError>;
+
+    /// Decodes the image like [`QoiDecode::qoi_decode`], calling `progress`
+    /// with the number of pixels decoded so far and the total after each row.
+    fn qoi_decode_with_progress(
+        &self,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(), QoiError>;
+
+    /// Decodes the image like [`QoiDecode::qoi_decode`], with each row
+    /// starting `stride` bytes after the previous one in `dest`. The bytes
+    /// between the end of a row and the start of the next are left unchanged.
+    ///
+    /// Returns [`QoiError::InputSize`] if `stride` is smaller than a row, and
+    /// [`QoiError::OutputTooSmall`] if `dest` doesn't reach the end of the
+    /// last row.
+    fn qoi_decode_strided(
+        &self,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+        stride: usize,
+    ) -> Result<(), QoiError>;
+
+    /// Decodes exactly `width * height` pixels like [`QoiDecode::qoi_decode`],
+    /// returning the number of input bytes consumed including the header and
+    /// padding. This allows decoding images concatenated in one buffer.
+    fn qoi_decode_counting(
+        &self,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError>;
+
+    #[cfg(feature = "alloc")]
     fn qoi_decode_to_vec(&self, channels: Option<Channels>) -> Result<Vec<u8>, QoiError>;
+
+    /// Like [`QoiDecode::qoi_decode_to_vec`], but replaces the contents of
+    /// `dest` with the decoded image, reusing its capacity.
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_into_vec(
+        &self,
+        channels: Option<Channels>,
+        dest: &mut Vec<u8>,
+    ) -> Result<(), QoiError>;
+
+    /// Like [`QoiDecode::qoi_decode_to_vec`], keeping the dimensions and
+    /// channels with the pixels.
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_to_image(&self, channels: Option<Channels>) -> Result<DecodedImage, QoiError>;
+
+    /// Decodes a thumbnail whose longest side is at most `max_dim` pixels,
+    /// by averaging square blocks of pixels as they're decoded. Only the
+    /// thumbnail is allocated, not the full image, though every opcode is
+    /// still read. Images which already fit are returned at full size.
+    /// Returns [`QoiError::TooBig`] if the thumbnail itself is larger than
+    /// [`Qoi::MAX_SIZE`].
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_thumbnail(&self, max_dim: u32) -> Result<DecodedImage, QoiError>;
+
+    /// Decodes only the alpha channel, with one byte per pixel. Images with 3
+    /// channels in their header are entirely 255.
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_alpha(&self) -> Result<Vec<u8>, QoiError>;
+
+    /// Decodes the red, green and blue channels into separate planes, each
+    /// with one byte per pixel. Alpha is discarded.
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_rgb_planar(&self) -> Result<[Vec<u8>; 3], QoiError>;
+
+    /// Decodes an image using the opcodes from the final QOI specification,
+    /// as written by other QOI encoders. The end marker is always checked,
+    /// and colour space values other than 0 and 1 are rejected with
+    /// [`QoiError::InvalidHeader`].
+    fn qoi_decode_standard(
+        &self,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError>;
+
+    /// Like [`QoiDecode::qoi_decode`], with additional checks enabled by
+    /// `options`.
+    fn qoi_decode_with_options(
+        &self,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+        options: DecodeOptions,
+    ) -> Result<(), QoiError>;
+
+    /// Like [`QoiDecode::qoi_decode`], writing the channels of each pixel in
+    /// `order`.
+    fn qoi_decode_with_order(
+        &self,
+        channels: Option<Channels>,
+        order: ChannelOrder,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError>;
+
+    /// Decodes the image to grayscale with one byte per pixel, taken from the
+    /// green channel. This reverses
+    /// [`QoiEncode::qoi_encode_gray`](crate::QoiEncode::qoi_encode_gray), and
+    /// discards the red, blue and alpha channels of other images.
+    fn qoi_decode_gray(&self, dest: impl AsMut<[u8]>) -> Result<(), QoiError>;
+
     fn load_qoi_header(&self) -> Result<QoiHeader, QoiError>;
+
+    /// Reads the header of an image from the final QOI specification, whose
+    /// colour space byte is 0 for
+    /// [`ColourSpace::Srgb`](crate::ColourSpace::Srgb) and 1 for
+    /// [`ColourSpace::Linear`](crate::ColourSpace::Linear).
+    fn load_qoi_standard_header(&self) -> Result<QoiHeader, QoiError>;
+
+    /// Reads only the width and height from the header, without validating
+    /// the rest of it.
+    fn qoi_dimensions(&self) -> Result<(u32, u32), QoiError>;
+
+    /// Checks that the image decodes fully without writing the pixels
+    /// anywhere, returning its header.
+    ///
+    /// Returns [`QoiError::UnexpectedEnd`] if there are too few pixels,
+    /// [`QoiError::InputSize`] if there are too many, and
+    /// [`QoiError::InvalidPadding`] if the padding isn't all zeroes. Bytes
+    /// after the padding are ignored, as they are when decoding.
+    fn qoi_validate(&self) -> Result<QoiHeader, QoiError>;
+
+    /// Counts the opcodes of each kind and the bytes they use, without
+    /// writing any pixels.
+    fn qoi_stats(&self) -> Result<QoiStats, QoiError>;
+
+    /// Like [`QoiDecode::qoi_stats`], formatted by [`QoiStats::to_csv`].
+    #[cfg(feature = "alloc")]
+    fn qoi_stats_csv(&self) -> Result<String, QoiError>;
+
+    /// Whether any pixel has an alpha value other than 255, regardless of the
+    /// header's channels. This includes pixels read from unused index cache
+    /// entries, which are transparent black. Images where this is `false` can
+    /// be decoded to 3 channels without losing anything.
+    fn qoi_uses_alpha(&self) -> Result<bool, QoiError>;
+
+    /// Creates a decoder which keeps only the last `ring_rows` decoded rows
+    /// in memory. The encoded image itself is still borrowed in full.
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_ring(
+        &self,
+        channels: Option<Channels>,
+        ring_rows: u32,
+    ) -> Result<QoiRingDecoder<'_>, QoiError>;
+
+    /// Creates an iterator which lazily decodes one pixel at a time, without
+    /// allocating.
+    fn qoi_pixels(&self) -> Result<QoiPixels<'_>, QoiError>;
+
+    /// Creates an iterator which lazily decodes each opcode as a pixel and
+    /// the number of times it's repeated, without expanding runs.
+    fn qoi_decode_runs(&self) -> Result<QoiRuns<'_>, QoiError>;
+
+    /// Creates an iterator which lazily decodes one row at a time. Use
+    /// [`QoiRows::next_row`] to borrow each row instead of copying it.
+    #[cfg(feature = "alloc")]
+    fn qoi_rows(&self, channels: Option<Channels>) -> Result<QoiRows<'_>, QoiError>;
+
+    /// Decodes one row at a time into a reused buffer, calling `f` with the
+    /// index of each row and its `width * channels` bytes.
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_rows(
+        &self,
+        channels: Option<Channels>,
+        f: impl FnMut(u32, &[u8]),
+    ) -> Result<(), QoiError>;
+
+    /// Decodes the image to `out` as a binary PPM (P6) for 3 channel images or
+    /// PAM (P7) for 4 channel images, which most image viewers can open.
+    #[cfg(feature = "std")]
+    fn qoi_decode_to_ppm(&self, out: &mut impl Write) -> Result<(), QoiError>;
+
+    /// Decodes the image to a binary PPM (P6) in memory. Alpha is dropped the
+    /// same way as when decoding a 4 channel image to 3 channels.
+    #[cfg(feature = "std")]
+    fn qoi_decode_to_ppm_vec(&self) -> Result<Vec<u8>, QoiError>;
+
+    /// Computes the FNV-1a hash of the decoded image without allocating it,
+    /// for use with [`QoiDecode::qoi_decode_checked`].
+    fn qoi_content_hash(&self, channels: Option<Channels>) -> Result<u64, QoiError>;
+
+    /// Decodes the image like [`QoiDecode::qoi_decode`] while hashing the
+    /// output, returning [`QoiError::ChecksumMismatch`] if it doesn't match
+    /// `expected_hash` from [`QoiDecode::qoi_content_hash`].
+    fn qoi_decode_checked(
+        &self,
+        expected_hash: u64,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError>;
+
+    /// Decodes only the `width` by `height` rectangle whose top left corner is
+    /// at `x`, `y`. `dest` must hold `width * height * channels` bytes.
+    ///
+    /// The opcodes before the rectangle still have to be read, but decoding
+    /// stops after its last row.
+    fn qoi_decode_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError>;
+
+    /// Decodes the image to one byte per pixel holding its luminance, as
+    /// computed by [`Pixel::luminance`]. `dest` must hold at least
+    /// `width * height` bytes.
+    fn qoi_decode_grayscale(&self, dest: impl AsMut<[u8]>) -> Result<(), QoiError>;
+
+    /// Copies the encoded image, replacing its header with `new` without
+    /// re-encoding the pixels.
+    ///
+    /// Returns [`QoiError::EmptyImage`] if either new dimension is zero,
+    /// [`QoiError::InputSize`] if the new dimensions don't have the same
+    /// number of pixels, and [`QoiError::Channels`] if `new` uses 3 channels
+    /// but the image contains non-opaque pixels.
+    #[cfg(feature = "alloc")]
+    fn qoi_rewrite_header(&self, new: &QoiHeader) -> Result<Vec<u8>, QoiError>;
+
+    /// Decodes the image to an RGB8 or RGBA8 [`image::DynamicImage`]
+    /// depending on the header's channels.
+    #[cfg(feature = "image")]
+    fn decode_to_dynamic_image(&self) -> Result<image::DynamicImage, QoiError>;
+
+    /// Like [`QoiDecode::qoi_decode`], recording the elapsed time and the
+    /// number of raw bytes decoded in `metrics`.
+    #[cfg(feature = "instrument")]
+    fn qoi_decode_instrumented(
+        &self,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+        metrics: &mut Metrics,
+    ) -> Result<(), QoiError>;
+
+    /// Decodes a container produced by
+    /// [`QoiEncode::qoi_encode_parallel`](crate::QoiEncode::qoi_encode_parallel),
+    /// decoding its stripes in parallel.
+    #[cfg(feature = "rayon")]
+    fn qoi_decode_parallel(
+        &self,
+        channels: Option<Channels>,
+    ) -> Result<(QoiHeader, Vec<u8>), QoiError>;
 }
 
 impl<S> QoiDecode for S
@@ -17,97 +718,774 @@ where
     fn qoi_decode(
         &self,
         channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError> {
+        self.qoi_decode_with_options(channels, dest, DecodeOptions::default())
+    }
+
+    fn qoi_decode_standard(
+        &self,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError> {
+        crate::standard::decode(self.as_ref(), channels, dest.as_mut())
+    }
+
+    fn qoi_decode_with_options(
+        &self,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+        options: DecodeOptions,
+    ) -> Result<(), QoiError> {
+        let input = options.strip_crc(self.as_ref())?;
+        let (header, mut decoder) = PixelDecoder::with_options(input, &options)?;
+        let channels = options.channels(channels, &header)?;
+        let dest = output_prefix(dest.as_mut(), header.raw_image_size(channels)?)?;
+
+        if let Some(max_total_pixels) = options.max_total_pixels {
+            let pixels = dest.len() / channels.len() as usize;
+            if pixels > max_total_pixels {
+                return Err(QoiError::TooBig {
+                    requested: pixels,
+                    max: max_total_pixels,
+                });
+            }
+        }
+
+        let transformed = options.transform != Transform::default();
+        match channels {
+            Channels::Three if transformed => {
+                decode_transformed::<3>(&mut decoder, &header, dest, &options)?
+            }
+            Channels::Four if transformed => {
+                decode_transformed::<4>(&mut decoder, &header, dest, &options)?
+            }
+            Channels::Three => decode_pixels::<3>(&mut decoder, dest, &options)?,
+            Channels::Four => decode_pixels::<4>(&mut decoder, dest, &options)?,
+        }
+
+        if options.strict && decoder.state.run > 0 {
+            return Err(QoiError::RunOverflow);
+        }
+
+        if options.strict || options.format_version == FormatVersion::Standard {
+            decoder.check_padding()?;
+        }
+
+        Ok(())
+    }
+
+    fn qoi_decode_with_cache(
+        &self,
+        channels: Option<Channels>,
+        cache: &mut [Pixel; 64],
+        previous: &mut Pixel,
         mut dest: impl AsMut<[u8]>,
     ) -> Result<(), QoiError> {
-        let mut reader = FallibleReader::new(self.as_ref());
-        let header = QoiHeader::new_from_slice(reader.read_slice(Qoi::HEADER_SIZE)?)?;
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
         let channels = channels.unwrap_or(header.channels);
+        let dest = output_prefix(dest.as_mut(), header.raw_image_size(channels)?)?;
 
-        let mut cache = [Pixel::default(); 64];
-        let mut run = 0u16;
-        let padding_pos = self.as_ref().len() - Qoi::PADDING_SIZE as usize;
-        let mut pixel = Pixel::new(0, 0, 0, 255);
-
-        for chunk in dest.as_mut().chunks_exact_mut(channels.len() as usize) {
-            if run > 0 {
-                run -= 1;
-            } else if reader.pos < padding_pos {
-                let b1 = reader.read()?;
-
-                if b1 & Qoi::MASK_2 == Qoi::INDEX {
-                    pixel = cache[(b1 ^ Qoi::INDEX) as usize];
-                } else if b1 & Qoi::MASK_3 == Qoi::RUN_8 {
-                    run = (b1 & 0x1f) as u16;
-                } else if b1 & Qoi::MASK_3 == Qoi::RUN_16 {
-                    let b2 = reader.read()?;
-                    run = ((((b1 & 0x1f) as u16) << 8) | b2 as u16) + 32;
-                } else if (b1 & Qoi::MASK_2) == Qoi::DIFF_8 {
-                    pixel.modify_r(((b1 >> 4) & 0x03) as i8 - 2);
-                    pixel.modify_g(((b1 >> 2) & 0x03) as i8 - 2);
-                    pixel.modify_b((b1 & 0x03) as i8 - 2);
-                } else if (b1 & Qoi::MASK_3) == Qoi::DIFF_16 {
-                    let b2 = reader.read()?;
-                    pixel.modify_r((b1 & 0x1f) as i8 - 16);
-                    pixel.modify_g((b2 >> 4) as i8 - 8);
-                    pixel.modify_b((b2 & 0x0f) as i8 - 8);
-                } else if (b1 & Qoi::MASK_4) == Qoi::DIFF_24 {
-                    let b2 = reader.read()?;
-                    let b3 = reader.read()?;
-
-                    pixel.modify_r((((b1 & 0x0f) << 1) | (b2 >> 7)) as i8 - 16);
-                    pixel.modify_g(((b2 & 0x7c) >> 2) as i8 - 16);
-                    pixel.modify_b((((b2 & 0x03) << 3) | ((b3 & 0xe0) >> 5)) as i8 - 16);
-                    pixel.modify_a((b3 & 0x1f) as i8 - 16);
-                } else if (b1 & Qoi::MASK_4) == Qoi::COLOR {
-                    if b1 & 8 > 0 {
-                        pixel.r = reader.read()?;
-                    }
-
-                    if b1 & 4 > 0 {
-                        pixel.g = reader.read()?;
-                    }
-
-                    if b1 & 2 > 0 {
-                        pixel.b = reader.read()?;
-                    }
-
-                    if b1 & 1 > 0 {
-                        pixel.a = reader.read()?;
-                    }
-                }
+        let state = &mut decoder.state;
+        state.cache = *cache;
+        state.cache[state.hash_mode.cache_index(previous)] = *previous;
+        state.pixel = *previous;
+
+        for chunk in dest.chunks_exact_mut(channels.len() as usize) {
+            write_pixel(chunk, decoder.next_pixel()?, channels)?;
+        }
+
+        *cache = decoder.state.cache;
+        *previous = decoder.state.pixel;
+
+        Ok(())
+    }
+
+    fn qoi_decode_with_progress(
+        &self,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+        let dest = output_prefix(dest.as_mut(), header.raw_image_size(channels)?)?;
+
+        let width = header.width as usize;
+        let total = width * header.height as usize;
+        let row_size = width * channels.len() as usize;
 
-                *(cache
-                    .get_mut(pixel.cache_index())
-                    .ok_or(QoiError::CacheIndex)?) = pixel;
+        for (y, row) in dest.chunks_exact_mut(row_size).enumerate() {
+            for chunk in row.chunks_exact_mut(channels.len() as usize) {
+                write_pixel(chunk, decoder.next_pixel()?, channels)?;
             }
 
-            *chunk.get_mut(0).ok_or(QoiError::OutputTooSmall)? = pixel.r;
-            *chunk.get_mut(1).ok_or(QoiError::OutputTooSmall)? = pixel.g;
-            *chunk.get_mut(2).ok_or(QoiError::OutputTooSmall)? = pixel.b;
+            progress((y + 1) * width, total);
+        }
+
+        Ok(())
+    }
+
+    fn qoi_decode_strided(
+        &self,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+        stride: usize,
+    ) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+
+        let height = header.height as usize;
+        let row_size = header.raw_image_size(channels)? / height;
+        if stride < row_size {
+            return Err(QoiError::InputSize);
+        }
 
-            if channels.len() == 4 {
-                *chunk.get_mut(3).ok_or(QoiError::OutputTooSmall)? = pixel.a;
+        let needed = stride
+            .checked_mul(height - 1)
+            .and_then(|start| start.checked_add(row_size))
+            .unwrap_or(usize::MAX);
+        let dest = output_prefix(dest.as_mut(), needed)?;
+
+        for y in 0..height {
+            let row = &mut dest[y * stride..y * stride + row_size];
+            for chunk in row.chunks_exact_mut(channels.len() as usize) {
+                write_pixel(chunk, decoder.next_pixel()?, channels)?;
             }
         }
 
         Ok(())
     }
 
-    fn qoi_decode_to_vec(&self, channels: Option<Channels>) -> Result<Vec<u8>, QoiError> {
-        let mut dest = Vec::new();
-        let header = QoiHeader::new_from_slice(self.as_ref())?;
+    fn qoi_decode_counting(
+        &self,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
         let channels = channels.unwrap_or(header.channels);
+        let dest = output_prefix(dest.as_mut(), header.raw_image_size(channels)?)?;
 
-        if header.raw_image_size(channels) > Qoi::MAX_SIZE {
-            return Err(QoiError::TooBig);
+        for chunk in dest.chunks_exact_mut(channels.len() as usize) {
+            write_pixel(chunk, decoder.next_pixel()?, channels)?;
         }
 
-        dest.resize(header.raw_image_size(channels), 0);
+        Ok(decoder.consumed())
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_to_vec(&self, channels: Option<Channels>) -> Result<Vec<u8>, QoiError> {
+        let header = QoiHeader::new_from_slice(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+        let mut dest = vec![0; header.checked_raw_image_size(channels)?];
         self.qoi_decode(Some(channels), &mut dest)?;
         Ok(dest)
     }
 
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_into_vec(
+        &self,
+        channels: Option<Channels>,
+        dest: &mut Vec<u8>,
+    ) -> Result<(), QoiError> {
+        let header = QoiHeader::new_from_slice(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+        let size = header.checked_raw_image_size(channels)?;
+
+        dest.clear();
+        dest.resize(size, 0);
+        self.qoi_decode(Some(channels), dest)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_to_image(&self, channels: Option<Channels>) -> Result<DecodedImage, QoiError> {
+        DecodedImage::decode(self.as_ref(), channels)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_thumbnail(&self, max_dim: u32) -> Result<DecodedImage, QoiError> {
+        DecodedImage::thumbnail(self.as_ref(), max_dim)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_alpha(&self) -> Result<Vec<u8>, QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let pixels = header.checked_raw_image_size(Channels::Three)? / 3;
+
+        // The stream is still walked so a corrupt image is reported the same
+        // way for either channel count.
+        let opaque = header.channels == Channels::Three;
+        let mut alpha = vec![0; pixels];
+        for value in &mut alpha {
+            let a = decoder.next_pixel()?.a;
+            *value = if opaque { 255 } else { a };
+        }
+
+        Ok(alpha)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_rgb_planar(&self) -> Result<[Vec<u8>; 3], QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let pixels = header.checked_raw_image_size(Channels::Three)? / 3;
+
+        let mut planes = [vec![0; pixels], vec![0; pixels], vec![0; pixels]];
+        let [r, g, b] = &mut planes;
+        for ((r, g), b) in r.iter_mut().zip(g.iter_mut()).zip(b.iter_mut()) {
+            let pixel = decoder.next_pixel()?;
+            (*r, *g, *b) = (pixel.r, pixel.g, pixel.b);
+        }
+
+        Ok(planes)
+    }
+
+    fn qoi_decode_with_order(
+        &self,
+        channels: Option<Channels>,
+        order: ChannelOrder,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+        let dest = output_prefix(dest.as_mut(), header.raw_image_size(channels)?)?;
+
+        for chunk in dest.chunks_exact_mut(channels.len() as usize) {
+            write_pixel_ordered(chunk, decoder.next_pixel()?, channels, order)?;
+        }
+
+        Ok(())
+    }
+
+    fn qoi_decode_gray(&self, mut dest: impl AsMut<[u8]>) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let pixels = header.raw_image_size(Channels::Three)? / 3;
+        let dest = output_prefix(dest.as_mut(), pixels)?;
+
+        for value in dest {
+            *value = decoder.next_pixel()?.g;
+        }
+
+        Ok(())
+    }
+
     fn load_qoi_header(&self) -> Result<QoiHeader, QoiError> {
         QoiHeader::new_from_slice(self.as_ref())
     }
+
+    fn load_qoi_standard_header(&self) -> Result<QoiHeader, QoiError> {
+        crate::standard::header(self.as_ref())
+    }
+
+    fn qoi_dimensions(&self) -> Result<(u32, u32), QoiError> {
+        let input = self
+            .as_ref()
+            .get(..12)
+            .ok_or(QoiError::InputSmallerThanHeader)?;
+
+        if &input[0..4] != b"qoif" {
+            return Err(QoiError::IncorrectHeaderMagic);
+        }
+
+        Ok((
+            u32::from_be_bytes(input[4..8].try_into().unwrap()),
+            u32::from_be_bytes(input[8..12].try_into().unwrap()),
+        ))
+    }
+
+    fn qoi_validate(&self) -> Result<QoiHeader, QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        decoder.validate((header.width as usize).saturating_mul(header.height as usize))?;
+        Ok(header)
+    }
+
+    fn qoi_stats(&self) -> Result<QoiStats, QoiError> {
+        crate::stats::stats(self.as_ref())
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_stats_csv(&self) -> Result<String, QoiError> {
+        Ok(self.qoi_stats()?.to_csv())
+    }
+
+    fn qoi_uses_alpha(&self) -> Result<bool, QoiError> {
+        let mut cursor = OpcodeCursor::new(self.as_ref())?;
+
+        while let Some((_, pixel)) = cursor.next_op()? {
+            if pixel.a != 255 {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_ring(
+        &self,
+        channels: Option<Channels>,
+        ring_rows: u32,
+    ) -> Result<QoiRingDecoder<'_>, QoiError> {
+        QoiRingDecoder::new(self.as_ref(), channels, ring_rows)
+    }
+
+    fn qoi_pixels(&self) -> Result<QoiPixels<'_>, QoiError> {
+        QoiPixels::new(self.as_ref())
+    }
+
+    fn qoi_decode_runs(&self) -> Result<QoiRuns<'_>, QoiError> {
+        QoiRuns::new(self.as_ref())
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_rows(&self, channels: Option<Channels>) -> Result<QoiRows<'_>, QoiError> {
+        QoiRows::new(self.as_ref(), channels)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_decode_rows(
+        &self,
+        channels: Option<Channels>,
+        mut f: impl FnMut(u32, &[u8]),
+    ) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+        let mut row = vec![0; header.checked_row_size(channels)?];
+
+        for y in 0..header.height {
+            for chunk in row.chunks_exact_mut(channels.len() as usize) {
+                write_pixel(chunk, decoder.next_pixel()?, channels)?;
+            }
+
+            f(y, &row);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    fn qoi_decode_to_ppm(&self, out: &mut impl Write) -> Result<(), QoiError> {
+        crate::ppm::decode_to_ppm(self.as_ref(), out)
+    }
+
+    #[cfg(feature = "std")]
+    fn qoi_decode_to_ppm_vec(&self) -> Result<Vec<u8>, QoiError> {
+        crate::ppm::decode_to_ppm_vec(self.as_ref())
+    }
+
+    fn qoi_content_hash(&self, channels: Option<Channels>) -> Result<u64, QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+        let pixels = (header.width as usize).saturating_mul(header.height as usize);
+        let mut hasher = Fnv1a::new();
+        let mut chunk = [0; 4];
+
+        for _ in 0..pixels {
+            write_pixel(&mut chunk, decoder.next_pixel()?, channels)?;
+            hasher.write(&chunk[..channels.len() as usize]);
+        }
+
+        Ok(hasher.finish())
+    }
+
+    fn qoi_decode_checked(
+        &self,
+        expected_hash: u64,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+        let dest = output_prefix(dest.as_mut(), header.raw_image_size(channels)?)?;
+        let mut hasher = Fnv1a::new();
+
+        for chunk in dest.chunks_exact_mut(channels.len() as usize) {
+            write_pixel(chunk, decoder.next_pixel()?, channels)?;
+            hasher.write(chunk);
+        }
+
+        if hasher.finish() != expected_hash {
+            return Err(QoiError::ChecksumMismatch);
+        }
+
+        Ok(())
+    }
+
+    fn qoi_decode_region(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let channels = channels.unwrap_or(header.channels);
+
+        if x as u64 + width as u64 > header.width as u64
+            || y as u64 + height as u64 > header.height as u64
+        {
+            return Err(QoiError::Region);
+        }
+
+        let image_width = header.width as usize;
+        let row_size = width as usize * channels.len() as usize;
+        let dest = output_prefix(dest.as_mut(), row_size * height as usize)?;
+
+        if row_size == 0 {
+            return Ok(());
+        }
+
+        decoder.skip(y as usize * image_width)?;
+
+        for (index, row) in dest.chunks_exact_mut(row_size).enumerate() {
+            decoder.skip(x as usize)?;
+
+            for chunk in row.chunks_exact_mut(channels.len() as usize) {
+                write_pixel(chunk, decoder.next_pixel()?, channels)?;
+            }
+
+            if index + 1 < height as usize {
+                decoder.skip(image_width - x as usize - width as usize)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn qoi_decode_grayscale(&self, mut dest: impl AsMut<[u8]>) -> Result<(), QoiError> {
+        let (header, mut decoder) = PixelDecoder::new(self.as_ref())?;
+        let pixels = (header.width as usize).saturating_mul(header.height as usize);
+        let dest = output_prefix(dest.as_mut(), pixels)?;
+
+        for value in dest {
+            *value = decoder.next_pixel()?.luminance();
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_rewrite_header(&self, new: &QoiHeader) -> Result<Vec<u8>, QoiError> {
+        let input = self.as_ref();
+        let (header, mut decoder) = PixelDecoder::new(input)?;
+        new.check_dimensions()?;
+
+        let pixels = (header.width as usize).saturating_mul(header.height as usize);
+        if (new.width as usize).saturating_mul(new.height as usize) != pixels {
+            return Err(QoiError::InputSize);
+        }
+
+        if new.channels == Channels::Three {
+            for _ in 0..pixels {
+                if decoder.next_pixel()?.a != 255 {
+                    return Err(QoiError::Channels);
+                }
+            }
+        }
+
+        let mut output = Vec::with_capacity(input.len());
+        output.extend_from_slice(&new.to_array());
+        output.extend_from_slice(&input[Qoi::HEADER_SIZE..]);
+        Ok(output)
+    }
+
+    #[cfg(feature = "image")]
+    fn decode_to_dynamic_image(&self) -> Result<image::DynamicImage, QoiError> {
+        crate::dynamic_image::decode_to_dynamic_image(self.as_ref())
+    }
+
+    #[cfg(feature = "instrument")]
+    fn qoi_decode_instrumented(
+        &self,
+        channels: Option<Channels>,
+        dest: impl AsMut<[u8]>,
+        metrics: &mut Metrics,
+    ) -> Result<(), QoiError> {
+        let header = QoiHeader::new_from_slice(self.as_ref())?;
+        let raw_image_size = header.raw_image_size(channels.unwrap_or(header.channels))?;
+        metrics.record(raw_image_size, || self.qoi_decode(channels, dest))
+    }
+
+    #[cfg(feature = "rayon")]
+    fn qoi_decode_parallel(
+        &self,
+        channels: Option<Channels>,
+    ) -> Result<(QoiHeader, Vec<u8>), QoiError> {
+        crate::parallel::decode_parallel(self.as_ref(), channels)
+    }
+}
+
+/// Options controlling how images are decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Return [`QoiError::TooBig`] instead of producing more than this many
+    /// pixels, regardless of what the header claims.
+    pub max_total_pixels: Option<usize>,
+    /// Return [`QoiError::InvalidPadding`] if the padding after the last
+    /// opcode isn't all zeroes, [`QoiError::UnexpectedEnd`] if the padding
+    /// is reached before every pixel has been decoded instead of repeating
+    /// the last pixel, or [`QoiError::RunOverflow`] if the last run continues
+    /// past the end of the image. Bytes after the padding are ignored.
+    pub strict: bool,
+    /// The hash used for the index cache, which must match the one the image
+    /// was encoded with.
+    pub hash_mode: HashMode,
+    /// Return [`QoiError::LossyChannelConversion`] instead of discarding the
+    /// alpha of non-opaque pixels when decoding to 3 channels.
+    pub deny_lossy_conversion: bool,
+    /// The marker expected after the opcodes, which must match the one the
+    /// image was encoded with. [`FormatVersion::Standard`] markers are always
+    /// checked, returning [`QoiError::InvalidPadding`] if they're wrong.
+    /// Bytes after the marker are ignored.
+    pub format_version: FormatVersion,
+    /// Return [`QoiError::ChannelMismatch`] if channels are requested which
+    /// differ from the header's, instead of converting them.
+    pub require_header_channels: bool,
+    /// Expect the trailing CRC-32 written by
+    /// [`EncodeOptions::with_crc`](crate::EncodeOptions::with_crc), returning
+    /// [`QoiError::ChecksumMismatch`] if it doesn't match. Only
+    /// [`QoiDecode::qoi_decode_with_options`] checks it, and
+    /// [`QoiStreamDecoder`] rejects it.
+    pub verify_crc: bool,
+    /// Multiply the colour channels by alpha when decoding to 4 channels, as
+    /// in [`Pixel::premultiplied`]. This has no effect when decoding to 3
+    /// channels.
+    pub premultiply_alpha: bool,
+    /// Flips the image while decoding. The output must hold the whole image,
+    /// or [`QoiError::OutputTooSmall`] is returned, so [`QoiStreamDecoder`]
+    /// rejects it.
+    pub transform: Transform,
+}
+
+/// Flips applied while decoding, see [`DecodeOptions::transform`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Transform {
+    /// Reverse the order of the rows, for APIs such as OpenGL where the
+    /// origin is the bottom left.
+    pub flip_v: bool,
+    /// Reverse the order of the pixels within each row.
+    pub flip_h: bool,
+}
+
+impl DecodeOptions {
+    /// The channels to decode to, `requested` or the header's if `None`.
+    fn channels(
+        &self,
+        requested: Option<Channels>,
+        header: &QoiHeader,
+    ) -> Result<Channels, QoiError> {
+        match requested {
+            Some(channels) if self.require_header_channels && channels != header.channels => {
+                Err(QoiError::ChannelMismatch)
+            }
+            Some(channels) => Ok(channels),
+            None => Ok(header.channels),
+        }
+    }
+
+    /// Returns `input` without its CRC-32 trailer after checking it, or
+    /// `input` unchanged if CRCs aren't being verified.
+    fn strip_crc<'a>(&self, input: &'a [u8]) -> Result<&'a [u8], QoiError> {
+        if !self.verify_crc {
+            return Ok(input);
+        }
+
+        let split = input.len().checked_sub(4).ok_or(QoiError::InputSize)?;
+        let (input, crc) = input.split_at(split);
+
+        if crate::hash::crc32(input).to_be_bytes() != crc {
+            return Err(QoiError::ChecksumMismatch);
+        }
+
+        Ok(input)
+    }
+}
+
+/// Reads from a [`Read`] through an internal buffer, so opcodes may be split
+/// across reads.
+#[cfg(feature = "std")]
+struct IoSource<R: Read> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    /// The number of bytes read before the start of `buf`.
+    offset: usize,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> IoSource<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: vec![0; 8 * 1024].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            offset: 0,
+        }
+    }
+
+    #[cold]
+    fn fill(&mut self) -> Result<(), QoiError> {
+        loop {
+            match self.inner.read(&mut self.buf) {
+                Ok(0) => return Err(QoiError::InputSize),
+                Ok(len) => {
+                    self.offset += self.len;
+                    self.pos = 0;
+                    self.len = len;
+                    return Ok(());
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Source for IoSource<R> {
+    #[inline(always)]
+    fn read(&mut self) -> Result<u8, QoiError> {
+        if self.pos == self.len {
+            self.fill()?;
+        }
+
+        let value = self.buf[self.pos];
+        self.pos += 1;
+        Ok(value)
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> usize {
+        self.offset + self.pos
+    }
+}
+
+/// Decodes an image from a [`Read`] one row at a time, so the encoded image
+/// doesn't have to be held in memory.
+///
+/// Reads are buffered, so the inner reader may be advanced past the end of
+/// the image.
+#[cfg(feature = "std")]
+pub struct QoiStreamDecoder<R: Read> {
+    source: IoSource<R>,
+    state: DecodeState,
+    header: QoiHeader,
+    channels: Channels,
+    options: DecodeOptions,
+    decoded_rows: u32,
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> QoiStreamDecoder<R> {
+    /// Reads the header from `reader`. The image is decoded using `channels`,
+    /// or the header's channels if `None`.
+    pub fn new(reader: R, channels: Option<Channels>) -> Result<Self, QoiError> {
+        Self::with_options(reader, channels, DecodeOptions::default())
+    }
+
+    /// Like [`QoiStreamDecoder::new`], applying `options` to each row.
+    ///
+    /// Rows are produced as they're decoded, so [`DecodeOptions::transform`]
+    /// and [`DecodeOptions::verify_crc`] can't be honoured and return
+    /// [`QoiError::UnsupportedFormat`]. Every other option applies.
+    pub fn with_options(
+        reader: R,
+        channels: Option<Channels>,
+        options: DecodeOptions,
+    ) -> Result<Self, QoiError> {
+        if options.transform != Transform::default() || options.verify_crc {
+            return Err(QoiError::UnsupportedFormat);
+        }
+
+        let mut source = IoSource::new(reader);
+        let mut header = [0; Qoi::HEADER_SIZE];
+
+        for byte in header.iter_mut() {
+            *byte = source.read().map_err(|e| match e {
+                QoiError::InputSize => QoiError::InputSmallerThanHeader,
+                e => e,
+            })?;
+        }
+
+        let header = QoiHeader::new_from_slice(&header)?;
+        let channels = options.channels(channels, &header)?;
+
+        Ok(Self {
+            source,
+            state: DecodeState::new(options.hash_mode),
+            header,
+            channels,
+            options,
+            decoded_rows: 0,
+        })
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+
+    /// Decodes the next row into `row`, which must hold at least
+    /// `width * channels` bytes. Returns `false` once every row has been
+    /// decoded.
+    ///
+    /// Returns [`QoiError::InputSize`] if the stream ends early, including
+    /// within the padding.
+    pub fn read_row(&mut self, row: &mut [u8]) -> Result<bool, QoiError> {
+        if self.decoded_rows == self.header.height {
+            return Ok(false);
+        }
+
+        if let Some(max_total_pixels) = self.options.max_total_pixels {
+            let total_pixels = (self.decoded_rows as usize + 1) * self.header.width as usize;
+            if total_pixels > max_total_pixels {
+                return Err(QoiError::TooBig {
+                    requested: total_pixels,
+                    max: max_total_pixels,
+                });
+            }
+        }
+
+        let channels = self.channels;
+        let row_size = self.header.width as usize * channels.len() as usize;
+        let row = output_prefix(row, row_size)?;
+
+        let check_alpha = self.options.deny_lossy_conversion && channels == Channels::Three;
+        let premultiply = self.options.premultiply_alpha && channels == Channels::Four;
+
+        for chunk in row.chunks_exact_mut(channels.len() as usize) {
+            let mut pixel = self.state.next_pixel(&mut self.source)?;
+
+            if check_alpha && pixel.a != 255 {
+                return Err(QoiError::LossyChannelConversion);
+            }
+
+            if premultiply {
+                pixel = pixel.premultiplied();
+            }
+
+            write_pixel(chunk, pixel, channels)?;
+        }
+
+        self.decoded_rows += 1;
+
+        if self.decoded_rows == self.header.height {
+            if self.options.strict && self.state.run > 0 {
+                return Err(QoiError::RunOverflow);
+            }
+
+            let format_version = self.options.format_version;
+            let check = self.options.strict || format_version == FormatVersion::Standard;
+
+            for &expected in format_version.end_marker() {
+                if self.source.read()? != expected && check {
+                    return Err(QoiError::InvalidPadding);
+                }
+            }
+        }
+
+        Ok(true)
+    }
 }
@@ -0,0 +1,131 @@
+use crate::{decode::PixelDecoder, Channels, Pixel, Qoi, QoiDecode, QoiError, QoiHeader};
+use alloc::{vec, vec::Vec};
+
+/// A decoded image along with its dimensions, from
+/// [`QoiDecode::qoi_decode_to_image`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    /// The channels of each pixel in `data`, which may differ from the
+    /// channels in the encoded header.
+    pub channels: Channels,
+    pub data: Vec<u8>,
+}
+
+impl DecodedImage {
+    pub(crate) fn decode(input: &[u8], channels: Option<Channels>) -> Result<Self, QoiError> {
+        let header = QoiHeader::new_from_slice(input)?;
+        let channels = channels.unwrap_or(header.channels);
+        let data = input.qoi_decode_to_vec(Some(channels))?;
+
+        Ok(Self {
+            width: header.width,
+            height: header.height,
+            channels,
+            data,
+        })
+    }
+
+    /// Decodes the image while averaging each square block of pixels, so the
+    /// longest side is at most `max_dim`. Blocks at the right and bottom
+    /// edges may be partial, and are averaged over the pixels they contain.
+    pub(crate) fn thumbnail(input: &[u8], max_dim: u32) -> Result<Self, QoiError> {
+        if max_dim == 0 {
+            return Err(QoiError::EmptyImage);
+        }
+
+        let (header, mut decoder) = PixelDecoder::new(input)?;
+        let channels = header.channels;
+        let channel_count = channels.len() as usize;
+
+        let block = header.width.max(header.height).div_ceil(max_dim);
+        let width = header.width.div_ceil(block);
+        let height = header.height.div_ceil(block);
+
+        let size = (width as usize)
+            .saturating_mul(height as usize)
+            .saturating_mul(channel_count);
+        if size > Qoi::MAX_SIZE {
+            return Err(QoiError::TooBig {
+                requested: size,
+                max: Qoi::MAX_SIZE,
+            });
+        }
+
+        let mut data = Vec::with_capacity(size);
+        // The channel totals for each block in the current row of blocks.
+        let mut sums = vec![[0u64; 4]; width as usize];
+        // Runs are added to each block they cover at once, so large images
+        // made up of long runs are quick to reduce.
+        let (mut pixel, mut repeats) = (Pixel::default(), 0);
+
+        for y in 0..header.height {
+            let mut x = 0;
+            while x < header.width {
+                if repeats == 0 {
+                    (pixel, repeats) = decoder.next_run()?;
+                }
+
+                let end = (header.width as usize).min((x as usize).saturating_add(repeats)) as u32;
+                repeats -= (end - x) as usize;
+
+                while x < end {
+                    let block_end = ((x / block + 1) as u64 * block as u64).min(end as u64) as u32;
+                    let count = (block_end - x) as u64;
+                    let sum = &mut sums[(x / block) as usize];
+                    sum[0] += pixel.r as u64 * count;
+                    sum[1] += pixel.g as u64 * count;
+                    sum[2] += pixel.b as u64 * count;
+                    sum[3] += pixel.a as u64 * count;
+                    x = block_end;
+                }
+            }
+
+            let last_row = y + 1 == header.height;
+            if (y + 1) % block != 0 && !last_row {
+                continue;
+            }
+
+            let rows = y % block + 1;
+            for (bx, sum) in sums.iter_mut().enumerate() {
+                let columns = (header.width - bx as u32 * block).min(block);
+                let count = rows as u64 * columns as u64;
+                for &total in &sum[..channel_count] {
+                    data.push(((total + count / 2) / count) as u8);
+                }
+                *sum = [0; 4];
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            channels,
+            data,
+        })
+    }
+
+    /// The channels of the pixel at `x`, `y`, or `None` if it's outside the
+    /// image.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<&[u8]> {
+        if x >= self.width {
+            return None;
+        }
+
+        let channels = self.channels.len() as usize;
+        let start = x as usize * channels;
+        self.row(y)?.get(start..start + channels)
+    }
+
+    /// The pixels of row `y`, or `None` if it's outside the image.
+    pub fn row(&self, y: u32) -> Option<&[u8]> {
+        if y >= self.height {
+            return None;
+        }
+
+        let row_size = self.width as usize * self.channels.len() as usize;
+        let start = y as usize * row_size;
+        self.data.get(start..start + row_size)
+    }
+}
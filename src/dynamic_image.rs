@@ -0,0 +1,67 @@
+use crate::{Channels, ColourSpace, QoiDecode, QoiEncode, QoiError, QoiHeader};
+use image::{DynamicImage, RgbImage, RgbaImage};
+
+/// An encoded QOI image, created from a [`DynamicImage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedImage(pub Vec<u8>);
+
+impl EncodedImage {
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for EncodedImage {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<&DynamicImage> for EncodedImage {
+    type Error = QoiError;
+
+    /// Encodes RGB8 images using 3 channels and RGBA8 images using 4.
+    /// Other formats return [`QoiError::UnsupportedFormat`].
+    fn try_from(image: &DynamicImage) -> Result<Self, Self::Error> {
+        let channels = match image {
+            DynamicImage::ImageRgb8(_) => Channels::Three,
+            DynamicImage::ImageRgba8(_) => Channels::Four,
+            _ => return Err(QoiError::UnsupportedFormat),
+        };
+
+        let encoded = image.as_bytes().qoi_encode_to_vec(
+            image.width(),
+            image.height(),
+            channels,
+            ColourSpace::Srgb,
+        )?;
+
+        Ok(Self(encoded))
+    }
+}
+
+impl TryFrom<DynamicImage> for EncodedImage {
+    type Error = QoiError;
+
+    fn try_from(image: DynamicImage) -> Result<Self, Self::Error> {
+        Self::try_from(&image)
+    }
+}
+
+pub(crate) fn decode_to_dynamic_image(input: &[u8]) -> Result<DynamicImage, QoiError> {
+    let header = QoiHeader::new_from_slice(input)?;
+    let (width, height) = (header.width, header.height);
+    let decoded = input.qoi_decode_to_vec(None)?;
+    let len = decoded.len();
+
+    // The buffer is exactly the right size, so these can't fail.
+    let image = match header.channels {
+        Channels::Three => RgbImage::from_raw(width, height, decoded).map(DynamicImage::ImageRgb8),
+        Channels::Four => RgbaImage::from_raw(width, height, decoded).map(DynamicImage::ImageRgba8),
+    };
+
+    image.ok_or(QoiError::OutputTooSmall {
+        needed: header.raw_image_size(header.channels)?,
+        got: len,
+    })
+}
@@ -1,4 +1,15 @@
-use crate::{Channels, FallibleWriter, Pixel, Qoi, QoiError, QoiHeader};
+#[cfg(feature = "instrument")]
+use crate::Metrics;
+#[cfg(feature = "metrics")]
+use crate::Timings;
+use crate::{
+    ChannelOrder, Channels, ColourSpace, EncodeStrategy, FallibleWriter, FormatVersion, HashMode,
+    Pixel, Qoi, QoiError, QoiHeader, Sink,
+};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::Write;
 
 trait IsBetween: PartialOrd
 where
@@ -13,7 +24,7 @@ where
 impl IsBetween for i16 {}
 
 #[inline(always)]
-fn write_run(writer: &mut FallibleWriter, run: &mut u16) -> Result<(), QoiError> {
+fn write_run(writer: &mut impl Sink, run: &mut u16) -> Result<(), QoiError> {
     if *run < 33 {
         *run -= 1;
         writer.write(Qoi::RUN_8 | (*run as u8))?;
@@ -44,7 +55,7 @@ fn can_diff_16(dr: i16, dg: i16, db: i16, da: i16) -> bool {
 }
 
 #[inline(always)]
-fn diff_16(dr: i16, dg: i16, db: i16, writer: &mut FallibleWriter) -> Result<(), QoiError> {
+fn diff_16(dr: i16, dg: i16, db: i16, writer: &mut impl Sink) -> Result<(), QoiError> {
     writer.write(Qoi::DIFF_16 | (dr + 16) as u8)?;
     writer.write(((dg + 8) << 4) as u8 | (db + 8) as u8)
 }
@@ -58,34 +69,781 @@ fn can_diff_24(dr: i16, dg: i16, db: i16, da: i16) -> bool {
 }
 
 #[inline(always)]
-fn diff_24(
-    dr: i16,
-    dg: i16,
-    db: i16,
-    da: i16,
-    writer: &mut FallibleWriter,
-) -> Result<(), QoiError> {
+fn diff_24(dr: i16, dg: i16, db: i16, da: i16, writer: &mut impl Sink) -> Result<(), QoiError> {
     writer.write(Qoi::DIFF_24 | ((dr + 16) >> 1) as u8)?;
     writer.write(((dr + 16) << 7) as u8 | ((dg + 16) << 2) as u8 | ((db + 16) >> 3) as u8)?;
     writer.write(((db + 16) << 5) as u8 | (da + 16) as u8)
 }
 
+/// The decoder adds deltas with wrapping, so a change such as 250 to 2 can
+/// still be encoded as +8 rather than falling back to COLOR. This is only
+/// shorter when COLOR would need at least 3 components.
+#[inline(always)]
+fn wrapped_diff_24(pixel: Pixel, previous: Pixel) -> Option<(i16, i16, i16, i16)> {
+    let dr = pixel.r.wrapping_sub(previous.r) as i8 as i16;
+    let dg = pixel.g.wrapping_sub(previous.g) as i8 as i16;
+    let db = pixel.b.wrapping_sub(previous.b) as i8 as i16;
+    let da = pixel.a.wrapping_sub(previous.a) as i8 as i16;
+
+    let changed = [dr, dg, db, da].iter().filter(|&&d| d != 0).count();
+    (changed >= 3 && can_diff_24(dr, dg, db, da)).then_some((dr, dg, db, da))
+}
+
+/// Writes the shortest DIFF or COLOR opcode which changes `previous` to
+/// `pixel`, which must differ.
+#[inline(always)]
+fn write_change(pixel: Pixel, previous: Pixel, writer: &mut impl Sink) -> Result<(), QoiError> {
+    let dr = pixel.r as i16 - previous.r as i16;
+    let dg = pixel.g as i16 - previous.g as i16;
+    let db = pixel.b as i16 - previous.b as i16;
+    let da = pixel.a as i16 - previous.a as i16;
+
+    if can_diff_8(dr, dg, db, da) {
+        writer.write(diff_8(dr, dg, db))?;
+    } else if can_diff_16(dr, dg, db, da) {
+        diff_16(dr, dg, db, writer)?;
+    } else if can_diff_24(dr, dg, db, da) {
+        diff_24(dr, dg, db, da, writer)?;
+    } else if let Some((wr, wg, wb, wa)) = wrapped_diff_24(pixel, previous) {
+        diff_24(wr, wg, wb, wa, writer)?;
+    } else {
+        // The command is filled in last to avoid extra branches.
+        let mut command = [Qoi::COLOR, 0, 0, 0, 0];
+        let mut len = 1;
+
+        if dr != 0 {
+            command[0] |= 8;
+            command[len] = pixel.r;
+            len += 1;
+        }
+
+        if dg != 0 {
+            command[0] |= 4;
+            command[len] = pixel.g;
+            len += 1;
+        }
+
+        if db != 0 {
+            command[0] |= 2;
+            command[len] = pixel.b;
+            len += 1;
+        }
+
+        if da != 0 {
+            command[0] |= 1;
+            command[len] = pixel.a;
+            len += 1;
+        }
+
+        // The pixel differs from the previous one, so at least one
+        // component must have been written.
+        debug_assert!(len > 1);
+        if len == 1 {
+            return Err(QoiError::InternalEncode);
+        }
+
+        writer.write_slice(&command[..len])?;
+    }
+
+    Ok(())
+}
+
+/// The encoding state machine, writing the opcodes for one pixel at a time.
+pub(crate) struct PixelEncoder {
+    cache: [Pixel; 64],
+    previous_pixel: Pixel,
+    run: u16,
+    hash_mode: HashMode,
+    format_version: FormatVersion,
+    strategy: EncodeStrategy,
+}
+
+impl PixelEncoder {
+    pub(crate) fn new(hash_mode: HashMode, format_version: FormatVersion) -> Self {
+        Self {
+            cache: [Pixel::default(); 64],
+            previous_pixel: Pixel::new(0, 0, 0, 255),
+            run: 0,
+            hash_mode,
+            format_version,
+            strategy: EncodeStrategy::Default,
+        }
+    }
+
+    fn from_options(options: &EncodeOptions) -> Self {
+        Self {
+            strategy: options.strategy,
+            ..Self::new(options.hash_mode, options.format_version)
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn reset(&mut self) {
+        *self = Self {
+            strategy: self.strategy,
+            ..Self::new(self.hash_mode, self.format_version)
+        };
+    }
+
+    #[inline(always)]
+    pub(crate) fn encode_pixel(
+        &mut self,
+        pixel: Pixel,
+        writer: &mut impl Sink,
+    ) -> Result<(), QoiError> {
+        let previous_pixel = self.previous_pixel;
+
+        if pixel == previous_pixel {
+            self.run += 1;
+
+            if self.run == 0x2020 {
+                write_run(writer, &mut self.run)?;
+            }
+
+            return Ok(());
+        }
+
+        self.flush(writer)?;
+
+        // The decoder still fills the cache, but it's never read from.
+        if self.strategy == EncodeStrategy::NoIndex {
+            write_change(pixel, previous_pixel, writer)?;
+            self.previous_pixel = pixel;
+            return Ok(());
+        }
+
+        let cache_index = self.hash_mode.cache_index(&pixel);
+
+        let cached = self
+            .cache
+            .get(cache_index)
+            .ok_or_else(|| QoiError::CacheIndex { pos: writer.pos() })?;
+
+        // INDEX is as short as any opcode, so it's always preferred, which
+        // is all `EncodeStrategy::PaletteBiased` asks for. The decoder caches
+        // every pixel whichever opcode produced it, so the cache only depends
+        // on the pixels and no choice of opcodes could make more of them
+        // available for INDEX.
+        if pixel == *cached {
+            writer.write(Qoi::INDEX | (cache_index as u8))?;
+        } else {
+            *(self
+                .cache
+                .get_mut(cache_index)
+                .ok_or_else(|| QoiError::CacheIndex { pos: writer.pos() })?) = pixel;
+
+            write_change(pixel, previous_pixel, writer)?;
+        }
+
+        self.previous_pixel = pixel;
+
+        Ok(())
+    }
+
+    /// Whether the last pixel was added to a pending run.
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    pub(crate) fn in_run(&self) -> bool {
+        self.run > 0
+    }
+
+    /// Adds `count` repeats of the previous pixel to the pending run, writing
+    /// it out whenever it reaches the longest run RUN_16 can hold.
+    #[cfg(feature = "simd")]
+    pub(crate) fn extend_run(
+        &mut self,
+        mut count: usize,
+        writer: &mut impl Sink,
+    ) -> Result<(), QoiError> {
+        while count > 0 {
+            let added = count.min((0x2020 - self.run) as usize);
+            self.run += added as u16;
+            count -= added;
+
+            if self.run == 0x2020 {
+                write_run(writer, &mut self.run)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes any pending run.
+    #[inline(always)]
+    pub(crate) fn flush(&mut self, writer: &mut impl Sink) -> Result<(), QoiError> {
+        if self.run > 0 {
+            write_run(writer, &mut self.run)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes any pending run and the end marker.
+    #[inline(always)]
+    pub(crate) fn finish(&mut self, writer: &mut impl Sink) -> Result<(), QoiError> {
+        self.flush(writer)?;
+        writer.write_slice(self.format_version.end_marker())
+    }
+}
+
+#[inline(always)]
+pub(crate) fn read_pixel(chunk: &[u8], channels: Channels, order: ChannelOrder) -> Pixel {
+    let [r, g, b, a] = order.positions(channels);
+    let a = if channels.len() == 4 { chunk[a] } else { 255 };
+    Pixel::new(chunk[r], chunk[g], chunk[b], a)
+}
+
+/// Encodes `src`, whose pixels are in `order`, to `writer` starting from
+/// `encoder`'s state.
+#[inline(always)]
+fn encode_to_sink(
+    encoder: &mut PixelEncoder,
+    src: &[u8],
+    header: &QoiHeader,
+    order: ChannelOrder,
+    writer: &mut impl Sink,
+) -> Result<(), QoiError> {
+    encode_phases(encoder, src, header, order, writer, || {})
+}
+
+/// Like [`encode_to_sink`], calling `checkpoint` after writing the header and
+/// after writing the pixels.
+#[inline(always)]
+fn encode_phases(
+    encoder: &mut PixelEncoder,
+    src: &[u8],
+    header: &QoiHeader,
+    order: ChannelOrder,
+    writer: &mut impl Sink,
+    mut checkpoint: impl FnMut(),
+) -> Result<(), QoiError> {
+    let channels = header.channels;
+
+    header.check_dimensions()?;
+
+    let raw_image_size = header.raw_image_size(channels)?;
+    if src.len() < raw_image_size {
+        return Err(QoiError::InputSize);
+    }
+    let src = &src[0..raw_image_size];
+
+    writer.write_slice(&header.to_array())?;
+    checkpoint();
+
+    #[cfg(feature = "simd")]
+    crate::simd::encode_pixels(encoder, src, channels, order, writer)?;
+
+    #[cfg(not(feature = "simd"))]
+    for chunk in src.chunks_exact(channels.len() as usize) {
+        encoder.encode_pixel(read_pixel(chunk, channels, order), writer)?;
+    }
+
+    encoder.flush(writer)?;
+    checkpoint();
+
+    encoder.finish(writer)
+}
+
+/// Encodes `src` into `dest` starting from `encoder`'s state, returning the
+/// encoded size.
+fn encode_slice(
+    encoder: &mut PixelEncoder,
+    src: &[u8],
+    header: &QoiHeader,
+    order: ChannelOrder,
+    dest: &mut [u8],
+) -> Result<usize, QoiError> {
+    let mut writer = FallibleWriter::new(dest);
+    encode_to_sink(encoder, src, header, order, &mut writer)
+        .map_err(|error| worst_case_output(error, header, encoder.format_version))?;
+    Ok(writer.pos)
+}
+
+/// The largest size `header`'s image could be encoded to in `format_version`.
+pub(crate) fn max_encoded_size(header: &QoiHeader, format_version: FormatVersion) -> usize {
+    (header.max_encoded_size() - Qoi::PADDING_SIZE as usize)
+        .saturating_add(format_version.end_marker().len())
+}
+
+/// Replaces the size needed by a [`QoiError::OutputTooSmall`] from a
+/// [`FallibleWriter`], which only covers the write which failed, with the
+/// worst case size of the whole image so the caller can retry once.
+pub(crate) fn worst_case_output(
+    error: QoiError,
+    header: &QoiHeader,
+    format_version: FormatVersion,
+) -> QoiError {
+    match error {
+        QoiError::OutputTooSmall { got, .. } => QoiError::OutputTooSmall {
+            needed: max_encoded_size(header, format_version),
+            got,
+        },
+        error => error,
+    }
+}
+
+/// Encodes 4 channel pixels from an iterator into `dest`, returning the
+/// encoded size.
+///
+/// Returns [`QoiError::InputSize`] if `pixels` doesn't yield exactly
+/// `width * height` pixels.
+pub fn qoi_encode_from_iter(
+    mut pixels: impl Iterator<Item = [u8; 4]>,
+    width: u32,
+    height: u32,
+    colour_space: ColourSpace,
+    mut dest: impl AsMut<[u8]>,
+) -> Result<usize, QoiError> {
+    let header = QoiHeader::new(width, height, Channels::Four, colour_space);
+    header.check_dimensions()?;
+
+    let too_small = |error| worst_case_output(error, &header, FormatVersion::Legacy);
+    let mut encoder = PixelEncoder::new(HashMode::default(), FormatVersion::Legacy);
+    let mut writer = FallibleWriter::new(dest.as_mut());
+    writer.write_slice(&header.to_array()).map_err(too_small)?;
+
+    for _ in 0..(width as u64 * height as u64) {
+        let [r, g, b, a] = pixels.next().ok_or(QoiError::InputSize)?;
+        encoder
+            .encode_pixel(Pixel::new(r, g, b, a), &mut writer)
+            .map_err(too_small)?;
+    }
+
+    if pixels.next().is_some() {
+        return Err(QoiError::InputSize);
+    }
+
+    encoder.finish(&mut writer).map_err(too_small)?;
+
+    Ok(writer.pos)
+}
+
+/// The number of pixels [`qoi_encode_in_place`] reads ahead of the one being
+/// encoded, which gives the output room to grow past the input it replaces.
+const IN_PLACE_LOOKAHEAD: usize = 64;
+
+/// Writes opcodes to the start of a buffer which still holds the pixels
+/// being encoded, refusing to write over any which haven't been read.
+struct InPlaceSink<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    /// The end of the bytes which have been read and can be overwritten.
+    read: usize,
+    /// Only checks the writes without changing `buf`.
+    dry_run: bool,
+}
+
+impl Sink for InPlaceSink<'_> {
+    #[inline(always)]
+    fn write(&mut self, value: u8) -> Result<(), QoiError> {
+        self.write_slice(&[value])
+    }
+
+    #[inline(always)]
+    fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError> {
+        let end = self.pos + slice.len();
+        if end > self.read {
+            return Err(QoiError::InPlaceOverlap);
+        }
+
+        if !self.dry_run {
+            self.buf[self.pos..end].copy_from_slice(slice);
+        }
+
+        self.pos = end;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+/// Encodes the opcodes for the `pixels` pixels at the start of `sink`'s
+/// buffer, without the header or padding.
+fn encode_in_place_pass(
+    sink: &mut InPlaceSink,
+    pixels: usize,
+    channels: Channels,
+) -> Result<(), QoiError> {
+    let len = channels.len() as usize;
+    let mut encoder = PixelEncoder::new(HashMode::default(), FormatVersion::Legacy);
+    let mut ahead = [Pixel::default(); IN_PLACE_LOOKAHEAD];
+    let mut read = 0;
+
+    for i in 0..pixels {
+        while read < pixels && read < i + IN_PLACE_LOOKAHEAD {
+            let chunk = &sink.buf[read * len..(read + 1) * len];
+            ahead[read % IN_PLACE_LOOKAHEAD] = read_pixel(chunk, channels, ChannelOrder::Rgba);
+            read += 1;
+        }
+
+        sink.read = read * len;
+        encoder.encode_pixel(ahead[i % IN_PLACE_LOOKAHEAD], sink)?;
+    }
+
+    encoder.flush(sink)
+}
+
+/// Encodes the raw pixels at the start of `buffer` into the same buffer,
+/// returning the encoded size.
+///
+/// The opcodes are written behind the pixels which are still to be read, and
+/// are moved along to make room for the header once every pixel is encoded,
+/// so `buffer` only has to hold the raw image or the encoded image, whichever
+/// is larger.
+///
+/// The encoded stream is checked before anything is written, and `buffer` is
+/// left unchanged if encoding fails. Returns:
+///
+/// - [`QoiError::InputSize`] if `buffer` is shorter than the raw image.
+/// - [`QoiError::InPlaceOverlap`] if the opcodes would overwrite pixels which
+///   haven't been read yet. This happens when the start of the image encodes
+///   to more than its raw size, for example with noisy 3 channel pixels.
+/// - [`QoiError::OutputTooSmall`] if the encoded image is larger than
+///   `buffer`.
+pub fn qoi_encode_in_place(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    channels: Channels,
+    colour_space: ColourSpace,
+) -> Result<usize, QoiError> {
+    let header = QoiHeader::new(width, height, channels, colour_space);
+    header.check_dimensions()?;
+
+    let raw_image_size = header.raw_image_size(channels)?;
+    if buffer.len() < raw_image_size {
+        return Err(QoiError::InputSize);
+    }
+
+    let pixels = raw_image_size / channels.len() as usize;
+    let mut sink = InPlaceSink {
+        buf: buffer,
+        pos: 0,
+        read: 0,
+        dry_run: true,
+    };
+    encode_in_place_pass(&mut sink, pixels, channels)?;
+
+    let end_marker = FormatVersion::Legacy.end_marker();
+    let opcodes = sink.pos;
+    let len = Qoi::HEADER_SIZE + opcodes + end_marker.len();
+    if len > buffer.len() {
+        return Err(QoiError::OutputTooSmall {
+            needed: len,
+            got: buffer.len(),
+        });
+    }
+
+    let mut sink = InPlaceSink {
+        buf: buffer,
+        pos: 0,
+        read: 0,
+        dry_run: false,
+    };
+    encode_in_place_pass(&mut sink, pixels, channels)?;
+
+    buffer.copy_within(..opcodes, Qoi::HEADER_SIZE);
+    buffer[..Qoi::HEADER_SIZE].copy_from_slice(&header.to_array());
+    buffer[len - end_marker.len()..len].copy_from_slice(end_marker);
+
+    Ok(len)
+}
+
+/// Counts the encoded bytes instead of writing them.
+struct CountingSink(usize);
+
+impl Sink for CountingSink {
+    #[inline(always)]
+    fn write(&mut self, _: u8) -> Result<(), QoiError> {
+        self.0 += 1;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError> {
+        self.0 += slice.len();
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> usize {
+        self.0
+    }
+}
+
+/// Replaces the contents of `dest` with the encoded image, reusing its
+/// capacity.
+#[cfg(feature = "alloc")]
+fn encode_to_vec(
+    encoder: &mut PixelEncoder,
+    src: &[u8],
+    header: &QoiHeader,
+    order: ChannelOrder,
+    dest: &mut Vec<u8>,
+) -> Result<(), QoiError> {
+    header.check_dimensions()?;
+
+    if !header.fits() {
+        return Err(QoiError::TooBig {
+            requested: max_encoded_size(header, encoder.format_version),
+            max: Qoi::MAX_SIZE,
+        });
+    }
+
+    let raw_image_size = header.raw_image_size(header.channels)?;
+
+    // Avoid allocating the destination when the input can't fill it.
+    if src.len() < raw_image_size {
+        return Err(QoiError::InputSize);
+    }
+
+    dest.clear();
+    dest.resize(max_encoded_size(header, encoder.format_version), 0);
+
+    let actual_size = encode_slice(encoder, src, header, order, dest)?;
+    dest.truncate(actual_size);
+
+    Ok(())
+}
+
+/// Options controlling how images are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// The hash used for the index cache. Images must be decoded with the
+    /// same mode, see [`DecodeOptions::hash_mode`](crate::DecodeOptions::hash_mode).
+    pub hash_mode: HashMode,
+    /// The order of the channels within each pixel of the raw image.
+    pub channel_order: ChannelOrder,
+    /// The marker written after the opcodes. Images must be decoded with the
+    /// same version, see
+    /// [`DecodeOptions::format_version`](crate::DecodeOptions::format_version).
+    pub format_version: FormatVersion,
+    /// Append a big endian CRC-32 of the encoded image after the end marker,
+    /// so the output is 4 bytes longer. This extends the format, so the
+    /// output must be decoded with
+    /// [`DecodeOptions::verify_crc`](crate::DecodeOptions::verify_crc).
+    pub with_crc: bool,
+    /// How opcodes are chosen, which doesn't affect how the image is decoded.
+    pub strategy: EncodeStrategy,
+}
+
+impl EncodeOptions {
+    /// Appends the CRC-32 of `dest[..len]` if requested, returning the new
+    /// length.
+    fn append_crc(&self, dest: &mut [u8], len: usize) -> Result<usize, QoiError> {
+        if !self.with_crc {
+            return Ok(len);
+        }
+
+        let crc = crate::hash::crc32(&dest[..len]);
+        let mut writer = FallibleWriter::new(dest);
+        writer.pos = len;
+        writer.write_slice(&crc.to_be_bytes())?;
+        Ok(writer.pos)
+    }
+}
+
 pub trait QoiEncode {
     fn qoi_encode(
         &self,
         width: u32,
         height: u32,
         channels: Channels,
-        colour_space: u8,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError>;
+
+    /// Encodes the image using the opcodes from the final QOI specification,
+    /// which other QOI decoders can read. `dest` may need up to 4 bytes more
+    /// than [`QoiHeader::max_encoded_size`] for the longer end marker.
+    ///
+    /// The specification's colour space byte is 0 for both
+    /// [`ColourSpace::Srgb`] and [`ColourSpace::SrgbLinearAlpha`], and 1 for
+    /// [`ColourSpace::Linear`].
+    fn qoi_encode_standard(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError>;
+
+    /// Encodes an image whose rows start `stride` bytes apart, such as a
+    /// rectangle within a larger buffer. Only the first `width * channels`
+    /// bytes of each row are read.
+    ///
+    /// Returns [`QoiError::InputSize`] if `stride` is smaller than a row, or
+    /// the input doesn't reach the end of the last row.
+    fn qoi_encode_strided(
+        &self,
+        width: u32,
+        height: u32,
+        stride: usize,
+        channels: Channels,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError>;
+
+    /// Encodes a grayscale image with one byte per pixel as a 3 channel image,
+    /// where each pixel has the same red, green and blue values. The output
+    /// can be read by any decoder, or back to grayscale with
+    /// [`QoiDecode::qoi_decode_gray`](crate::QoiDecode::qoi_decode_gray).
+    fn qoi_encode_gray(
+        &self,
+        width: u32,
+        height: u32,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError>;
+
+    /// Encodes the image starting from `cache` and `previous` instead of an
+    /// empty cache, and updates them for the next image. This helps when
+    /// encoding a sequence of similar frames.
+    ///
+    /// The output isn't a standalone QOI image. It can only be decoded by
+    /// [`QoiDecode::qoi_decode_with_cache`](crate::QoiDecode::qoi_decode_with_cache)
+    /// with the same starting state, which both store `previous` in the cache
+    /// before starting.
+    #[allow(clippy::too_many_arguments)]
+    fn qoi_encode_with_cache(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        cache: &mut [Pixel; 64],
+        previous: &mut Pixel,
         dest: impl AsMut<[u8]>,
     ) -> Result<usize, QoiError>;
 
+    /// Like [`QoiEncode::qoi_encode`], using `options`.
+    fn qoi_encode_with_options(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+        options: EncodeOptions,
+    ) -> Result<usize, QoiError>;
+
+    /// Encodes the image into a buffer sized for the worst case.
+    ///
+    /// Returns [`QoiError::TooBig`] with the worst case size if it's larger
+    /// than [`Qoi::MAX_SIZE`](crate::Qoi::MAX_SIZE), which is checked before
+    /// allocating, or [`QoiError::InputSize`] if `self` is shorter than
+    /// `width * height * channels` bytes.
+    #[cfg(feature = "alloc")]
     fn qoi_encode_to_vec(
         &self,
         width: u32,
         height: u32,
         channels: Channels,
-        colour_space: u8,
+        colour_space: ColourSpace,
+    ) -> Result<Vec<u8>, QoiError>;
+
+    /// Encodes the image into an internal buffer and writes it to `writer`,
+    /// returning the number of bytes written.
+    #[cfg(feature = "std")]
+    fn qoi_encode_to_writer(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        writer: impl Write,
+    ) -> Result<usize, QoiError>;
+
+    /// Like [`QoiEncode::qoi_encode_to_vec`], using `options`.
+    #[cfg(feature = "alloc")]
+    fn qoi_encode_to_vec_with_options(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        options: EncodeOptions,
+    ) -> Result<Vec<u8>, QoiError>;
+
+    /// Like [`QoiEncode::qoi_encode_to_vec`], but starts with a `capacity`
+    /// byte output and grows it as needed instead of allocating for the worst
+    /// case. Without a `capacity` this starts with half of the raw image size,
+    /// which most images fit within.
+    ///
+    /// This lowers peak memory use when the output is much smaller than the
+    /// worst case, at the cost of reallocating when it isn't.
+    #[cfg(feature = "alloc")]
+    fn qoi_encode_growable(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        capacity: Option<usize>,
+    ) -> Result<Vec<u8>, QoiError>;
+
+    /// Like [`QoiEncode::qoi_encode`], also returning how long each phase of
+    /// the encode took.
+    #[cfg(feature = "metrics")]
+    fn qoi_encode_timed(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<(usize, Timings), QoiError>;
+
+    /// Like [`QoiEncode::qoi_encode`], recording the elapsed time and the
+    /// number of raw bytes encoded in `metrics`.
+    #[cfg(feature = "instrument")]
+    fn qoi_encode_instrumented(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+        metrics: &mut Metrics,
+    ) -> Result<usize, QoiError>;
+
+    /// Computes the exact size [`QoiEncode::qoi_encode`] would produce, by
+    /// encoding the image without writing the output.
+    fn qoi_encoded_len(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+    ) -> Result<usize, QoiError>;
+
+    /// The same as [`QoiEncode::qoi_encoded_len`], which doesn't depend on the
+    /// colour space.
+    #[deprecated(note = "use `qoi_encoded_len`, which computes the same size")]
+    fn qoi_estimate_size(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+    ) -> Result<usize, QoiError>;
+
+    /// Encodes horizontal stripes of the image in parallel, producing a
+    /// container which can only be decoded by
+    /// [`QoiDecode::qoi_decode_parallel`](crate::QoiDecode::qoi_decode_parallel).
+    ///
+    /// The output isn't a standard QOI stream. Each stripe starts from the
+    /// initial encoder state, which costs a little compression.
+    #[cfg(feature = "rayon")]
+    fn qoi_encode_parallel(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
     ) -> Result<Vec<u8>, QoiError>;
 }
 
@@ -98,122 +856,578 @@ where
         width: u32,
         height: u32,
         channels: Channels,
-        colour_space: u8,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError> {
+        self.qoi_encode_with_options(
+            width,
+            height,
+            channels,
+            colour_space,
+            dest,
+            EncodeOptions::default(),
+        )
+    }
+
+    fn qoi_encode_with_options(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
         mut dest: impl AsMut<[u8]>,
+        options: EncodeOptions,
     ) -> Result<usize, QoiError> {
-        let src = self.as_ref();
         let header = QoiHeader::new(width, height, channels, colour_space);
-        let mut writer = FallibleWriter::new(dest.as_mut());
+        let dest = dest.as_mut();
+        let len = encode_slice(
+            &mut PixelEncoder::from_options(&options),
+            self.as_ref(),
+            &header,
+            options.channel_order,
+            dest,
+        )?;
+        options.append_crc(dest, len)
+    }
 
-        let mut cache = [Pixel::default(); 64];
-        let mut previous_pixel = Pixel::new(0, 0, 0, 255);
-        let mut run = 0u16;
-        let raw_image_size = header.raw_image_size(channels);
-        if raw_image_size < (channels.len() as usize) || src.len() < raw_image_size {
+    fn qoi_encode_standard(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        crate::standard::encode(self.as_ref(), &header, dest.as_mut())
+    }
+
+    fn qoi_encode_strided(
+        &self,
+        width: u32,
+        height: u32,
+        stride: usize,
+        channels: Channels,
+        colour_space: ColourSpace,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        header.check_dimensions()?;
+
+        let row_size = header.raw_image_size(channels)? / height as usize;
+        let src = self.as_ref();
+        let needed = stride
+            .checked_mul(height as usize - 1)
+            .and_then(|start| start.checked_add(row_size))
+            .ok_or(QoiError::InputSize)?;
+        if stride < row_size || src.len() < needed {
             return Err(QoiError::InputSize);
         }
-        let src = &src[0..raw_image_size];
-        let last_chunk_index = src.len() / channels.len() as usize - 1;
 
-        writer.write_slice(&header.to_array())?;
+        let too_small = |error| worst_case_output(error, &header, FormatVersion::Legacy);
+        let mut encoder = PixelEncoder::new(HashMode::default(), FormatVersion::Legacy);
+        let mut writer = FallibleWriter::new(dest.as_mut());
+        writer.write_slice(&header.to_array()).map_err(too_small)?;
 
-        for (index, chunk) in src.chunks_exact(channels.len() as usize).enumerate() {
-            let a = if channels.len() == 4 { chunk[3] } else { 255 };
-            let pixel = Pixel::new(chunk[0], chunk[1], chunk[2], a);
+        for y in 0..height as usize {
+            let row = &src[y * stride..y * stride + row_size];
+            for chunk in row.chunks_exact(channels.len() as usize) {
+                encoder
+                    .encode_pixel(read_pixel(chunk, channels, ChannelOrder::Rgba), &mut writer)
+                    .map_err(too_small)?;
+            }
+        }
 
-            if pixel == previous_pixel {
-                run += 1;
+        encoder.finish(&mut writer).map_err(too_small)?;
 
-                if run == 0x2020 || index == last_chunk_index {
-                    write_run(&mut writer, &mut run)?;
-                }
-            } else {
-                if run > 0 {
-                    write_run(&mut writer, &mut run)?;
-                }
+        Ok(writer.pos)
+    }
 
-                let cache_index = pixel.cache_index();
-
-                if pixel == *cache.get(cache_index).ok_or(QoiError::CacheIndex)? {
-                    writer.write(Qoi::INDEX | (cache_index as u8))?;
-                } else {
-                    *(cache.get_mut(cache_index).ok_or(QoiError::CacheIndex)?) = pixel;
-
-                    let dr = pixel.r as i16 - previous_pixel.r as i16;
-                    let dg = pixel.g as i16 - previous_pixel.g as i16;
-                    let db = pixel.b as i16 - previous_pixel.b as i16;
-                    let da = pixel.a as i16 - previous_pixel.a as i16;
-
-                    if can_diff_8(dr, dg, db, da) {
-                        writer.write(diff_8(dr, dg, db))?;
-                    } else if can_diff_16(dr, dg, db, da) {
-                        diff_16(dr, dg, db, &mut writer)?;
-                    } else if can_diff_24(dr, dg, db, da) {
-                        diff_24(dr, dg, db, da, &mut writer)?;
-                    } else {
-                        let mut command = Qoi::COLOR;
-
-                        // The command is written last to avoid extra branches.
-                        let command_pos = writer.pos;
-                        writer.pos += 1;
-
-                        if dr != 0 {
-                            command |= 8;
-                            writer.write(pixel.r)?;
-                        }
-
-                        if dg != 0 {
-                            command |= 4;
-                            writer.write(pixel.g)?;
-                        }
-
-                        if db != 0 {
-                            command |= 2;
-                            writer.write(pixel.b)?;
-                        }
-
-                        if da != 0 {
-                            command |= 1;
-                            writer.write(pixel.a)?;
-                        }
-
-                        writer.write_at(command_pos, command)?;
-                    }
-                }
+    fn qoi_encode_gray(
+        &self,
+        width: u32,
+        height: u32,
+        colour_space: ColourSpace,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError> {
+        let header = QoiHeader::new(width, height, Channels::Three, colour_space);
+        header.check_dimensions()?;
 
-                previous_pixel = pixel;
-            }
+        let pixels = header.raw_image_size(Channels::Three)? / 3;
+        let src = self.as_ref().get(..pixels).ok_or(QoiError::InputSize)?;
+
+        let too_small = |error| worst_case_output(error, &header, FormatVersion::Legacy);
+        let mut encoder = PixelEncoder::new(HashMode::default(), FormatVersion::Legacy);
+        let mut writer = FallibleWriter::new(dest.as_mut());
+        writer.write_slice(&header.to_array()).map_err(too_small)?;
+
+        for &value in src {
+            encoder
+                .encode_pixel(Pixel::new(value, value, value, 255), &mut writer)
+                .map_err(too_small)?;
         }
 
-        writer.write_slice(&[0; Qoi::PADDING_SIZE as usize])?;
+        encoder.finish(&mut writer).map_err(too_small)?;
 
         Ok(writer.pos)
     }
 
+    fn qoi_encode_with_cache(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        cache: &mut [Pixel; 64],
+        previous: &mut Pixel,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<usize, QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        let mut encoder = PixelEncoder::new(HashMode::default(), FormatVersion::Legacy);
+        encoder.cache = *cache;
+        encoder.cache[encoder.hash_mode.cache_index(previous)] = *previous;
+        encoder.previous_pixel = *previous;
+
+        let len = encode_slice(
+            &mut encoder,
+            self.as_ref(),
+            &header,
+            ChannelOrder::Rgba,
+            dest.as_mut(),
+        )?;
+
+        *cache = encoder.cache;
+        *previous = encoder.previous_pixel;
+
+        Ok(len)
+    }
+
+    #[cfg(feature = "alloc")]
     fn qoi_encode_to_vec(
         &self,
         width: u32,
         height: u32,
         channels: Channels,
-        colour_space: u8,
+        colour_space: ColourSpace,
     ) -> Result<Vec<u8>, QoiError> {
-        let size = (width as usize)
-            .saturating_mul(height as usize)
-            .saturating_mul(channels.len() as usize)
-            .saturating_add(Qoi::HEADER_SIZE as usize)
-            .saturating_add(Qoi::PADDING_SIZE as usize);
+        self.qoi_encode_to_vec_with_options(
+            width,
+            height,
+            channels,
+            colour_space,
+            EncodeOptions::default(),
+        )
+    }
 
-        if size > Qoi::MAX_SIZE {
-            return Err(QoiError::TooBig);
-        }
+    #[cfg(feature = "std")]
+    fn qoi_encode_to_writer(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        mut writer: impl Write,
+    ) -> Result<usize, QoiError> {
+        let encoded = self.qoi_encode_to_vec(width, height, channels, colour_space)?;
+        writer.write_all(&encoded)?;
+        Ok(encoded.len())
+    }
 
+    #[cfg(feature = "alloc")]
+    fn qoi_encode_to_vec_with_options(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        options: EncodeOptions,
+    ) -> Result<Vec<u8>, QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
         let mut dest = Vec::new();
-        dest.resize(size, 0);
+        encode_to_vec(
+            &mut PixelEncoder::from_options(&options),
+            self.as_ref(),
+            &header,
+            options.channel_order,
+            &mut dest,
+        )?;
 
-        let actual_size =
-            self.qoi_encode(width, height, channels, colour_space, dest.as_mut_slice())?;
-        dest.resize(actual_size, 0);
+        if options.with_crc {
+            let crc = crate::hash::crc32(&dest);
+            dest.extend_from_slice(&crc.to_be_bytes());
+        }
 
         Ok(dest)
     }
+
+    #[cfg(feature = "alloc")]
+    fn qoi_encode_growable(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        capacity: Option<usize>,
+    ) -> Result<Vec<u8>, QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        header.check_dimensions()?;
+
+        if !header.fits() {
+            return Err(QoiError::TooBig {
+                requested: header.max_encoded_size(),
+                max: Qoi::MAX_SIZE,
+            });
+        }
+
+        let raw_image_size = header.raw_image_size(channels)?;
+        let src = self.as_ref();
+
+        // Avoid allocating the destination when the input can't fill it.
+        if src.len() < raw_image_size {
+            return Err(QoiError::InputSize);
+        }
+
+        let capacity =
+            capacity.unwrap_or(raw_image_size / 2 + Qoi::HEADER_SIZE + Qoi::PADDING_SIZE as usize);
+        let mut dest = Vec::with_capacity(capacity);
+        encode_to_sink(
+            &mut PixelEncoder::new(HashMode::default(), FormatVersion::Legacy),
+            src,
+            &header,
+            ChannelOrder::Rgba,
+            &mut dest,
+        )?;
+
+        Ok(dest)
+    }
+
+    #[cfg(feature = "metrics")]
+    fn qoi_encode_timed(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<(usize, Timings), QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        let mut encoder = PixelEncoder::new(HashMode::default(), FormatVersion::Legacy);
+        let mut writer = FallibleWriter::new(dest.as_mut());
+
+        let start = std::time::Instant::now();
+        let mut checkpoints = [start; 2];
+        let mut phase = 0;
+        encode_phases(
+            &mut encoder,
+            self.as_ref(),
+            &header,
+            ChannelOrder::Rgba,
+            &mut writer,
+            || {
+                checkpoints[phase] = std::time::Instant::now();
+                phase += 1;
+            },
+        )
+        .map_err(|error| worst_case_output(error, &header, FormatVersion::Legacy))?;
+        let end = std::time::Instant::now();
+
+        let timings = Timings {
+            header: checkpoints[0] - start,
+            body: checkpoints[1] - checkpoints[0],
+            padding: end - checkpoints[1],
+        };
+
+        Ok((writer.pos, timings))
+    }
+
+    #[cfg(feature = "instrument")]
+    fn qoi_encode_instrumented(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        dest: impl AsMut<[u8]>,
+        metrics: &mut Metrics,
+    ) -> Result<usize, QoiError> {
+        let raw_image_size =
+            QoiHeader::new(width, height, channels, colour_space).raw_image_size(channels)?;
+        metrics.record(raw_image_size, || {
+            self.qoi_encode(width, height, channels, colour_space, dest)
+        })
+    }
+
+    fn qoi_encoded_len(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+    ) -> Result<usize, QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        let mut sink = CountingSink(0);
+        encode_to_sink(
+            &mut PixelEncoder::new(HashMode::default(), FormatVersion::Legacy),
+            self.as_ref(),
+            &header,
+            ChannelOrder::Rgba,
+            &mut sink,
+        )?;
+        Ok(sink.0)
+    }
+
+    fn qoi_estimate_size(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+    ) -> Result<usize, QoiError> {
+        self.qoi_encoded_len(width, height, channels, ColourSpace::Srgb)
+    }
+
+    #[cfg(feature = "rayon")]
+    fn qoi_encode_parallel(
+        &self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+    ) -> Result<Vec<u8>, QoiError> {
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        crate::parallel::encode_parallel(self.as_ref(), &header)
+    }
+}
+
+/// Grows the vector to fit each encoded byte.
+#[cfg(feature = "alloc")]
+impl Sink for Vec<u8> {
+    #[inline(always)]
+    fn write(&mut self, value: u8) -> Result<(), QoiError> {
+        self.push(value);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError> {
+        self.extend_from_slice(slice);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Writes each encoded byte straight to the inner writer.
+#[cfg(feature = "std")]
+struct IoSink<W: Write> {
+    inner: W,
+    pos: usize,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Sink for IoSink<W> {
+    #[inline(always)]
+    fn write(&mut self, value: u8) -> Result<(), QoiError> {
+        self.inner.write_all(&[value])?;
+        self.pos += 1;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError> {
+        self.inner.write_all(slice)?;
+        self.pos += slice.len();
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+/// Encodes an image incrementally to a [`Write`], so neither the raw image nor
+/// the encoded output has to be held in memory all at once.
+///
+/// Opcodes are written to the inner writer as they're produced, so wrapping
+/// it in a [`std::io::BufWriter`] is recommended.
+#[cfg(feature = "std")]
+pub struct QoiStreamEncoder<W: Write> {
+    sink: IoSink<W>,
+    encoder: PixelEncoder,
+    channels: Channels,
+    remaining_pixels: Option<usize>,
+    partial: [u8; 4],
+    partial_len: usize,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> QoiStreamEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            sink: IoSink {
+                inner: writer,
+                pos: 0,
+            },
+            encoder: PixelEncoder::new(HashMode::default(), FormatVersion::Legacy),
+            channels: Channels::Four,
+            remaining_pixels: None,
+            partial: [0; 4],
+            partial_len: 0,
+        }
+    }
+
+    /// Writes the header. This must be called once before any pixels are
+    /// written.
+    pub fn write_header(
+        &mut self,
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+    ) -> Result<(), QoiError> {
+        if self.remaining_pixels.is_some() {
+            return Err(QoiError::InvalidHeader);
+        }
+
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        header.check_dimensions()?;
+        self.sink.write_slice(&header.to_array())?;
+        self.channels = channels;
+        self.remaining_pixels = Some((width as usize).saturating_mul(height as usize));
+
+        Ok(())
+    }
+
+    /// Encodes raw pixel data using the channels given in the header. Pixels
+    /// may be split across calls.
+    pub fn write_pixels(&mut self, mut raw: &[u8]) -> Result<(), QoiError> {
+        if self.remaining_pixels.is_none() {
+            return Err(QoiError::InvalidHeader);
+        }
+
+        let channels = self.channels.len() as usize;
+
+        while !raw.is_empty() {
+            let needed = channels - self.partial_len;
+
+            if self.partial_len > 0 || raw.len() < needed {
+                let count = needed.min(raw.len());
+                self.partial[self.partial_len..self.partial_len + count]
+                    .copy_from_slice(&raw[..count]);
+                self.partial_len += count;
+                raw = &raw[count..];
+
+                if self.partial_len == channels {
+                    self.partial_len = 0;
+                    let pixel = read_pixel(&self.partial, self.channels, ChannelOrder::Rgba);
+                    self.encode_pixel(pixel)?;
+                }
+
+                continue;
+            }
+
+            let whole = raw.len() - raw.len() % channels;
+            for chunk in raw[..whole].chunks_exact(channels) {
+                self.encode_pixel(read_pixel(chunk, self.channels, ChannelOrder::Rgba))?;
+            }
+            raw = &raw[whole..];
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn encode_pixel(&mut self, pixel: Pixel) -> Result<(), QoiError> {
+        let remaining = self
+            .remaining_pixels
+            .as_mut()
+            .ok_or(QoiError::InvalidHeader)?;
+        *remaining = remaining.checked_sub(1).ok_or(QoiError::InputSize)?;
+        self.encoder.encode_pixel(pixel, &mut self.sink)
+    }
+
+    /// Writes any pending run and the padding, returning the inner writer.
+    ///
+    /// Returns [`QoiError::InputSize`] if fewer pixels than the header
+    /// describes were written.
+    pub fn finish(mut self) -> Result<W, QoiError> {
+        if self.remaining_pixels != Some(0) || self.partial_len != 0 {
+            return Err(QoiError::InputSize);
+        }
+
+        self.encoder.finish(&mut self.sink)?;
+        self.sink.inner.flush()?;
+
+        Ok(self.sink.inner)
+    }
+}
+
+/// An encoder for many images in a row, which reuses its buffers instead of
+/// allocating for each image.
+#[cfg(feature = "alloc")]
+pub struct QoiEncoder {
+    encoder: PixelEncoder,
+    scratch: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl Default for QoiEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl QoiEncoder {
+    pub fn new() -> Self {
+        Self {
+            encoder: PixelEncoder::new(HashMode::default(), FormatVersion::Legacy),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Clears the encoder's state and the contents of its internal buffer,
+    /// keeping the buffer's capacity. The state is also reset before each
+    /// image is encoded.
+    pub fn reset(&mut self) {
+        self.encoder.reset();
+        self.scratch.clear();
+    }
+
+    /// Replaces the contents of `output` with the encoded image, reusing its
+    /// capacity.
+    pub fn encode_into(
+        &mut self,
+        raw: &[u8],
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+        output: &mut Vec<u8>,
+    ) -> Result<(), QoiError> {
+        self.encoder.reset();
+        let header = QoiHeader::new(width, height, channels, colour_space);
+        encode_to_vec(&mut self.encoder, raw, &header, ChannelOrder::Rgba, output)
+    }
+
+    /// Encodes the image into the encoder's internal buffer, which is
+    /// overwritten by the next call.
+    pub fn encode(
+        &mut self,
+        raw: &[u8],
+        width: u32,
+        height: u32,
+        channels: Channels,
+        colour_space: ColourSpace,
+    ) -> Result<&[u8], QoiError> {
+        let mut scratch = core::mem::take(&mut self.scratch);
+        let result = self.encode_into(raw, width, height, channels, colour_space, &mut scratch);
+        self.scratch = scratch;
+        result?;
+
+        Ok(&self.scratch)
+    }
 }
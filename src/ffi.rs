@@ -0,0 +1,200 @@
+//! A C interface to the encoder and decoder.
+//!
+//! Build the crate as a `cdylib` or `staticlib` with the `ffi` feature, for
+//! example using `cargo rustc --release --features ffi --crate-type cdylib`.
+//!
+//! Functions returning a buffer return null on failure and store an error
+//! code in `error` if it isn't null. The codes are:
+//!
+//! | Code | Error |
+//! |------|-------|
+//! | 0 | No error |
+//! | 1 | [`QoiError::InputSmallerThanHeader`] |
+//! | 2 | [`QoiError::IncorrectHeaderMagic`] |
+//! | 3 | [`QoiError::Channels`] |
+//! | 4 | [`QoiError::InputSize`] |
+//! | 5 | [`QoiError::OutputTooSmall`] |
+//! | 6 | [`QoiError::InvalidHeader`] |
+//! | 7 | [`QoiError::TooBig`] |
+//! | 8 | [`QoiError::Io`] |
+//! | 9 | [`QoiError::CacheIndex`] |
+//! | 10 | [`QoiError::Region`] |
+//! | 11 | [`QoiError::ChecksumMismatch`] |
+//! | 12 | [`QoiError::InvalidPadding`] |
+//! | 13 | [`QoiError::EmptyImage`] |
+//! | 14 | [`QoiError::UnsupportedFormat`] |
+//! | 15 | [`QoiError::UnexpectedEnd`] |
+//! | 16 | A null pointer was passed |
+//! | 17 | [`QoiError::LossyChannelConversion`] |
+//! | 18 | [`QoiError::InternalEncode`] |
+//! | 19 | [`QoiError::ChannelMismatch`] |
+//! | 20 | [`QoiError::InPlaceOverlap`] |
+//! | 21 | [`QoiError::RunOverflow`] |
+//!
+//! Returned buffers must be released with [`qoi_free`].
+
+use crate::{Channels, ColourSpace, QoiDecode, QoiEncode, QoiError, QoiHeader};
+use core::{ffi::c_int, ptr, slice};
+
+/// The error code for a null pointer argument.
+pub const QOI_ERROR_NULL: c_int = 16;
+
+/// Describes a decoded image, matching `qoi_desc` from the reference
+/// implementation.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QoiDesc {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u8,
+    pub colorspace: u8,
+}
+
+fn error_code(error: &QoiError) -> c_int {
+    match error {
+        QoiError::InputSmallerThanHeader => 1,
+        QoiError::IncorrectHeaderMagic => 2,
+        QoiError::Channels => 3,
+        QoiError::InputSize => 4,
+        QoiError::OutputTooSmall { .. } => 5,
+        QoiError::InvalidHeader => 6,
+        QoiError::TooBig { .. } => 7,
+        QoiError::Io(_) => 8,
+        QoiError::CacheIndex { .. } => 9,
+        QoiError::Region => 10,
+        QoiError::ChecksumMismatch => 11,
+        QoiError::InvalidPadding => 12,
+        QoiError::EmptyImage => 13,
+        QoiError::UnsupportedFormat => 14,
+        QoiError::UnexpectedEnd { .. } => 15,
+        QoiError::LossyChannelConversion => 17,
+        QoiError::InternalEncode => 18,
+        QoiError::ChannelMismatch => 19,
+        QoiError::InPlaceOverlap => 20,
+        QoiError::RunOverflow => 21,
+    }
+}
+
+unsafe fn set_error(error: *mut c_int, code: c_int) {
+    if !error.is_null() {
+        *error = code;
+    }
+}
+
+/// Leaks `buf`, returning a pointer to be released by [`qoi_free`].
+fn into_raw(buf: Vec<u8>) -> *mut u8 {
+    Box::into_raw(buf.into_boxed_slice()) as *mut u8
+}
+
+/// Encodes `width * height * channels` bytes of RGB or RGBA pixels from
+/// `data`, storing the encoded length in `out_len`.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `width * height * channels` bytes.
+/// `out_len` must be valid for writes, and `error` must be null or valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn qoi_encode_raw(
+    data: *const u8,
+    width: u32,
+    height: u32,
+    channels: u8,
+    out_len: *mut usize,
+    error: *mut c_int,
+) -> *mut u8 {
+    if data.is_null() || out_len.is_null() {
+        set_error(error, QOI_ERROR_NULL);
+        return ptr::null_mut();
+    }
+
+    let result = Channels::try_from(channels).and_then(|channels| {
+        let header = QoiHeader::new(width, height, channels, ColourSpace::Srgb);
+        header.check_dimensions()?;
+        let size = header.checked_raw_image_size(channels)?;
+        slice::from_raw_parts(data, size).qoi_encode_to_vec(
+            width,
+            height,
+            channels,
+            ColourSpace::Srgb,
+        )
+    });
+
+    match result {
+        Ok(encoded) => {
+            set_error(error, 0);
+            *out_len = encoded.len();
+            into_raw(encoded)
+        }
+        Err(e) => {
+            set_error(error, error_code(&e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Decodes `len` bytes of a QOI image from `data` to `channels` channels, or
+/// the header's channels if `channels` is 0. The header is stored in
+/// `out_desc`, with its channels replaced by those decoded.
+///
+/// The returned buffer holds `width * height * channels` bytes.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes. `out_desc` must be valid
+/// for writes, and `error` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn qoi_decode_raw(
+    data: *const u8,
+    len: usize,
+    channels: u8,
+    out_desc: *mut QoiDesc,
+    error: *mut c_int,
+) -> *mut u8 {
+    if data.is_null() || out_desc.is_null() {
+        set_error(error, QOI_ERROR_NULL);
+        return ptr::null_mut();
+    }
+
+    let input = slice::from_raw_parts(data, len);
+    let result = (match channels {
+        0 => Ok(None),
+        channels => Channels::try_from(channels).map(Some),
+    })
+    .and_then(|channels| {
+        let header = input.load_qoi_header()?;
+        let channels = channels.unwrap_or(header.channels);
+        let decoded = input.qoi_decode_to_vec(Some(channels))?;
+        Ok((header, channels, decoded))
+    });
+
+    match result {
+        Ok((header, channels, decoded)) => {
+            set_error(error, 0);
+            *out_desc = QoiDesc {
+                width: header.width,
+                height: header.height,
+                channels: channels.len(),
+                colorspace: header.colour_space.into(),
+            };
+            into_raw(decoded)
+        }
+        Err(e) => {
+            set_error(error, error_code(&e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases a buffer returned by [`qoi_encode_raw`] or [`qoi_decode_raw`].
+///
+/// # Safety
+///
+/// `data` must be null or a buffer returned by this library which hasn't been
+/// freed, and `len` must be its length.
+#[no_mangle]
+pub unsafe extern "C" fn qoi_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::QoiError;
+use std::time::{Duration, Instant};
+
+/// Throughput metrics accumulated across instrumented encode and decode calls.
+///
+/// Only successful calls are recorded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Metrics {
+    /// The total number of raw image bytes encoded or decoded.
+    pub bytes: u64,
+    /// The total time spent in the recorded calls, in nanoseconds.
+    pub nanos: u64,
+    /// The number of recorded calls.
+    pub calls: u64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The total time spent in the recorded calls.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos)
+    }
+
+    /// The average number of raw image bytes processed per second.
+    pub fn bytes_per_second(&self) -> f64 {
+        if self.nanos == 0 {
+            return 0.0;
+        }
+        self.bytes as f64 * 1_000_000_000.0 / self.nanos as f64
+    }
+
+    #[inline]
+    pub(crate) fn record<T>(
+        &mut self,
+        bytes: usize,
+        f: impl FnOnce() -> Result<T, QoiError>,
+    ) -> Result<T, QoiError> {
+        let start = Instant::now();
+        let result = f()?;
+        let nanos = start.elapsed().as_nanos().min(u64::MAX as u128) as u64;
+
+        self.bytes = self.bytes.saturating_add(bytes as u64);
+        self.nanos = self.nanos.saturating_add(nanos);
+        self.calls += 1;
+
+        Ok(result)
+    }
+}
+
+/// How long each phase of a single encode took, from
+/// [`QoiEncode::qoi_encode_timed`](crate::QoiEncode::qoi_encode_timed).
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    /// Checking the input and writing the header.
+    pub header: Duration,
+    /// Encoding the pixels, including any pending run.
+    pub body: Duration,
+    /// Writing the end marker.
+    pub padding: Duration,
+}
+
+#[cfg(feature = "metrics")]
+impl Timings {
+    /// The time taken by every phase.
+    pub fn total(&self) -> Duration {
+        self.header + self.body + self.padding
+    }
+}
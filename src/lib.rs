@@ -1,28 +1,160 @@
-use std::{error::Error, fmt::Display};
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::fmt::Display;
+
+mod analyze;
+pub use analyze::{qoi_analyze_batch, BatchRecommendation};
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+
+mod atlas;
+pub use atlas::AtlasReader;
+#[cfg(feature = "alloc")]
+pub use atlas::AtlasWriter;
+
+mod compare;
+pub use compare::compare_decoded;
+
+mod cursor;
+pub use cursor::{Opcode, OpcodeCursor};
 
 mod decode;
-pub use decode::QoiDecode;
+#[cfg(feature = "std")]
+pub use decode::QoiStreamDecoder;
+pub use decode::{DecodeOptions, QoiDecode, Transform};
+
+#[cfg(feature = "alloc")]
+mod decoded;
+#[cfg(feature = "alloc")]
+pub use decoded::DecodedImage;
 
 mod encode;
-pub use encode::QoiEncode;
+#[cfg(feature = "alloc")]
+pub use encode::QoiEncoder;
+#[cfg(feature = "std")]
+pub use encode::QoiStreamEncoder;
+pub use encode::{qoi_encode_from_iter, qoi_encode_in_place, EncodeOptions, QoiEncode};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+mod hash;
+
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "rayon")]
+pub use parallel::qoi_validate_all;
+
+mod pixels;
+pub use pixels::QoiPixels;
+
+#[cfg(feature = "std")]
+mod ppm;
+
+#[cfg(feature = "serde")]
+mod serde;
 
-#[derive(Debug)]
+#[cfg(feature = "simd")]
+mod simd;
+
+mod standard;
+
+mod stats;
+pub use stats::{OpcodeStats, QoiStats};
+
+#[cfg(feature = "alloc")]
+mod ring;
+#[cfg(feature = "alloc")]
+pub use ring::QoiRingDecoder;
+
+mod runs;
+pub use runs::QoiRuns;
+
+#[cfg(feature = "alloc")]
+mod rows;
+#[cfg(feature = "alloc")]
+pub use rows::QoiRows;
+
+#[cfg(feature = "image")]
+mod dynamic_image;
+#[cfg(feature = "image")]
+pub use dynamic_image::EncodedImage;
+
+#[cfg(feature = "instrument")]
+mod instrument;
+#[cfg(feature = "instrument")]
+pub use instrument::Metrics;
+#[cfg(feature = "metrics")]
+pub use instrument::Timings;
+
+#[cfg(feature = "verify")]
+mod verify;
+#[cfg(feature = "verify")]
+pub use verify::VerifyingReader;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum QoiError {
     InputSmallerThanHeader,
     IncorrectHeaderMagic,
     Channels,
     InputSize,
-    OutputTooSmall,
+    OutputTooSmall {
+        /// The number of bytes the output needs to hold. When encoding this is
+        /// the worst case size of the whole image, so retrying with a buffer
+        /// this large will succeed.
+        needed: usize,
+        /// The size of the output which was provided.
+        got: usize,
+    },
     InvalidHeader,
-    TooBig,
-    Io(std::io::Error),
-    CacheIndex,
+    TooBig {
+        /// The size which was needed, saturating at `usize::MAX`. This is in
+        /// bytes, except for [`DecodeOptions::max_total_pixels`] where it's
+        /// in pixels.
+        requested: usize,
+        /// The largest size which is allowed.
+        max: usize,
+    },
+    #[cfg(feature = "std")]
+    Io(IoError),
+    CacheIndex {
+        /// The offset of the opcode, in the encoded image.
+        pos: usize,
+    },
+    Region,
+    ChecksumMismatch,
+    InvalidPadding,
+    EmptyImage,
+    UnsupportedFormat,
+    UnexpectedEnd {
+        /// The offset in the input where another opcode was expected, or of
+        /// the opcode which extends into the padding.
+        pos: usize,
+    },
+    LossyChannelConversion,
+    InternalEncode,
+    ChannelMismatch,
+    InPlaceOverlap,
+    RunOverflow,
 }
 
-impl Error for QoiError {}
+#[cfg(feature = "std")]
+impl std::error::Error for QoiError {
+    /// Returns the underlying [`std::io::Error`] for [`QoiError::Io`].
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(inner) => Some(inner.inner()),
+            _ => None,
+        }
+    }
+}
 
 impl Display for QoiError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::InputSmallerThanHeader => {
                 f.write_str("The input is too small to contain a header")
@@ -30,24 +162,86 @@ impl Display for QoiError {
             Self::IncorrectHeaderMagic => f.write_str("The header magic value i wrong"),
             Self::Channels => f.write_str("The number of channels is invalid"),
             Self::InputSize => f.write_str("The input size is invalid"),
-            Self::OutputTooSmall => f.write_str("The output buffer is too small"),
+            Self::OutputTooSmall { needed, got } => write!(
+                f,
+                "The output buffer is too small, {needed} bytes are needed but it's {got} bytes"
+            ),
             Self::InvalidHeader => f.write_str("The header is invalid"),
-            Self::TooBig => f.write_str("The image size is too big"),
-            Self::Io(inner) => {
-                f.write_fmt(format_args!("An I/O error occurred: {}", inner.to_string()))
+            Self::TooBig { requested, max } => write!(
+                f,
+                "The image size is too big, {requested} is requested but the limit is {max}"
+            ),
+            #[cfg(feature = "std")]
+            Self::Io(inner) => f.write_fmt(format_args!("An I/O error occurred: {}", inner)),
+            Self::CacheIndex { pos } => {
+                f.write_fmt(format_args!("The cache index at byte {} is invalid", pos))
+            }
+            Self::Region => f.write_str("The region is outside of the image"),
+            Self::ChecksumMismatch => f.write_str("The checksum doesn't match"),
+            Self::InvalidPadding => f.write_str("The padding is invalid"),
+            Self::EmptyImage => f.write_str("The image has no width or height"),
+            Self::UnsupportedFormat => f.write_str("The pixel format is unsupported"),
+            Self::UnexpectedEnd { pos } => f.write_fmt(format_args!(
+                "The input ended at byte {} before every pixel was decoded",
+                pos
+            )),
+            Self::LossyChannelConversion => {
+                f.write_str("Decoding to 3 channels would discard the alpha channel")
             }
-            Self::CacheIndex => f.write_str("The cache index is invalid"),
+            Self::InternalEncode => f.write_str("The encoder reached an invalid state"),
+            Self::ChannelMismatch => {
+                f.write_str("The requested channels don't match the header's channels")
+            }
+            Self::InPlaceOverlap => {
+                f.write_str("Encoding in place would overwrite pixels which haven't been read")
+            }
+            Self::RunOverflow => f.write_str("A run continues past the last pixel of the image"),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for QoiError {
     fn from(error: std::io::Error) -> Self {
-        QoiError::Io(error)
+        QoiError::Io(IoError(std::sync::Arc::new(error)))
+    }
+}
+
+/// A shared [`std::io::Error`] which can be cloned, and is compared by its
+/// [`std::io::ErrorKind`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct IoError(std::sync::Arc<std::io::Error>);
+
+#[cfg(feature = "std")]
+impl IoError {
+    pub fn kind(&self) -> std::io::ErrorKind {
+        self.0.kind()
+    }
+
+    pub fn inner(&self) -> &std::io::Error {
+        &self.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind() == other.kind()
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[cfg(feature = "std")]
+impl Eq for IoError {}
+
+#[cfg(feature = "std")]
+impl Display for IoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Channels {
     Three,
     Four,
@@ -66,22 +260,187 @@ impl TryFrom<u8> for Channels {
     }
 }
 
+impl From<Channels> for u8 {
+    fn from(channels: Channels) -> Self {
+        channels.count()
+    }
+}
+
+impl Display for Channels {
+    /// Formats the channels as `rgb` or `rgba`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Three => f.write_str("rgb"),
+            Self::Four => f.write_str("rgba"),
+        }
+    }
+}
+
+impl core::str::FromStr for Channels {
+    type Err = QoiError;
+
+    /// Parses `3` or `rgb`, and `4` or `rgba`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "3" || s.eq_ignore_ascii_case("rgb") {
+            Ok(Self::Three)
+        } else if s == "4" || s.eq_ignore_ascii_case("rgba") {
+            Ok(Self::Four)
+        } else {
+            Err(QoiError::Channels)
+        }
+    }
+}
+
 impl Channels {
+    /// The number of channels, 3 or 4.
     #[inline(always)]
-    fn len(&self) -> u8 {
+    pub fn count(self) -> u8 {
         match self {
             Self::Three => 3,
             Self::Four => 4,
         }
     }
+
+    #[inline(always)]
+    fn len(&self) -> u8 {
+        self.count()
+    }
+}
+
+/// The order of the channels within each pixel of raw image data. When there
+/// are 3 channels the alpha channel is omitted, so [`ChannelOrder::Argb`] is
+/// the same as [`ChannelOrder::Rgba`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ChannelOrder {
+    #[default]
+    Rgba,
+    Bgra,
+    Argb,
+}
+
+impl ChannelOrder {
+    /// The positions of the red, green, blue and alpha channels.
+    #[inline(always)]
+    fn positions(self, channels: Channels) -> [usize; 4] {
+        match (self, channels) {
+            (Self::Rgba, _) | (Self::Argb, Channels::Three) => [0, 1, 2, 3],
+            (Self::Bgra, _) => [2, 1, 0, 3],
+            (Self::Argb, Channels::Four) => [1, 2, 3, 0],
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct Pixel {
-    r: u8,
-    g: u8,
-    b: u8,
-    a: u8,
+/// How the colour channels should be interpreted. This doesn't affect how
+/// images are encoded or decoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColourSpace {
+    /// Every channel is sRGB.
+    Srgb,
+    /// The colour channels are sRGB and the alpha channel is linear.
+    SrgbLinearAlpha,
+    /// Every channel is linear.
+    Linear,
+}
+
+impl TryFrom<u8> for ColourSpace {
+    type Error = QoiError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let colour_space = match value {
+            0x00 => Self::Srgb,
+            0x01 => Self::SrgbLinearAlpha,
+            0x0f => Self::Linear,
+            _ => return Err(QoiError::InvalidHeader),
+        };
+        Ok(colour_space)
+    }
+}
+
+impl From<ColourSpace> for u8 {
+    fn from(colour_space: ColourSpace) -> Self {
+        match colour_space {
+            ColourSpace::Srgb => 0x00,
+            ColourSpace::SrgbLinearAlpha => 0x01,
+            ColourSpace::Linear => 0x0f,
+        }
+    }
+}
+
+/// The hash used to find a pixel's position in the index cache. The encoder
+/// and decoder must use the same mode, which isn't recorded in the header.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum HashMode {
+    /// The hash used by previous versions of this crate, see
+    /// [`Pixel::cache_index_xor`].
+    #[default]
+    Xor,
+    /// The hash used by the reference implementation, which collides less
+    /// often, see [`Pixel::cache_index`].
+    Weighted,
+}
+
+/// How the encoder chooses between opcodes. Every strategy produces streams
+/// which any decoder can read.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum EncodeStrategy {
+    /// Use whichever opcode is shortest.
+    #[default]
+    Default,
+    /// Never look pixels up in the index cache, and use DIFF or COLOR opcodes
+    /// instead of INDEX. This is faster for noisy images where the cache
+    /// rarely hits, but larger for most others.
+    NoIndex,
+    /// Prefer INDEX over any DIFF or COLOR opcode whenever the pixel is
+    /// cached, for images with few colours. This produces the same streams as
+    /// [`EncodeStrategy::Default`], which already does so as INDEX is as
+    /// short as any opcode, and the cache only depends on the pixels rather
+    /// than the opcodes chosen.
+    PaletteBiased,
+}
+
+impl HashMode {
+    #[inline(always)]
+    fn cache_index(self, pixel: &Pixel) -> usize {
+        match self {
+            Self::Xor => pixel.cache_index_xor(),
+            Self::Weighted => pixel.cache_index(),
+        }
+    }
+}
+
+/// The marker written after the opcodes. Only the marker differs, the
+/// opcodes are the same for both versions.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum FormatVersion {
+    /// 4 zero bytes, as written by previous versions of this crate.
+    #[default]
+    Legacy,
+    /// The 8 byte marker `00 00 00 00 00 00 00 01` from the final
+    /// specification.
+    Standard,
+}
+
+impl FormatVersion {
+    #[inline(always)]
+    fn end_marker(self) -> &'static [u8] {
+        match self {
+            Self::Legacy => &[0; Qoi::PADDING_SIZE as usize],
+            Self::Standard => &[0, 0, 0, 0, 0, 0, 0, 1],
+        }
+    }
+}
+
+/// An RGBA pixel.
+///
+/// The default pixel, `(0, 0, 0, 0)`, is what the index cache is initialised
+/// with. The encoder and decoder instead start from a previous pixel of
+/// `(0, 0, 0, 255)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pixel {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
 }
 
 impl Default for Pixel {
@@ -98,12 +457,38 @@ impl Default for Pixel {
 
 impl Pixel {
     #[inline]
-    fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
         Self { r, g, b, a }
     }
 
+    /// The Rec. 601 luma of the pixel, computed as
+    /// `(299 * r + 587 * g + 114 * b + 500) / 1000`. Alpha is ignored.
     #[inline]
-    fn cache_index(&self) -> usize {
+    pub fn luminance(&self) -> u8 {
+        let luma = 299 * self.r as u32 + 587 * self.g as u32 + 114 * self.b as u32;
+        ((luma + 500) / 1000) as u8
+    }
+
+    /// The pixel with its colour channels multiplied by its alpha, computed
+    /// as `(c as u16 * a as u16 / 255) as u8` which rounds down.
+    #[inline]
+    pub fn premultiplied(&self) -> Pixel {
+        let multiply = |c: u8| (c as u16 * self.a as u16 / 255) as u8;
+        Pixel::new(multiply(self.r), multiply(self.g), multiply(self.b), self.a)
+    }
+
+    /// The position of the pixel in the 64 entry index cache when using
+    /// [`HashMode::Weighted`], `(r * 3 + g * 5 + b * 7 + a * 11) % 64`.
+    #[inline]
+    pub fn cache_index(&self) -> usize {
+        (self.r as usize * 3 + self.g as usize * 5 + self.b as usize * 7 + self.a as usize * 11)
+            % 64
+    }
+
+    /// The position of the pixel in the 64 entry index cache when using
+    /// [`HashMode::Xor`], `(r ^ g ^ b ^ a) % 64`.
+    #[inline]
+    pub fn cache_index_xor(&self) -> usize {
         (self.r ^ self.g ^ self.b ^ self.a) as usize % 64
     }
 
@@ -133,32 +518,48 @@ pub struct Qoi;
 impl Qoi {
     const HEADER_SIZE: usize = 14;
     const PADDING_SIZE: u8 = 4;
-    const MAX_SIZE: usize = 1024 * 1024 * 1024;
 
-    const INDEX: u8 = 0;
+    /// The largest raw or encoded image size in bytes which will be
+    /// allocated, see [`QoiHeader::fits`].
+    pub const MAX_SIZE: usize = 1024 * 1024 * 1024;
+
+    /// The largest number of pixels an image can have while still fitting
+    /// within [`Qoi::MAX_SIZE`] when encoded, regardless of its channels.
+    pub const MAX_PIXELS: usize =
+        (Self::MAX_SIZE - Self::HEADER_SIZE - Self::PADDING_SIZE as usize) / 5;
 
-    const RUN_8: u8 = 0b0100_0000;
-    const RUN_16: u8 = 0b0110_0000;
-    const DIFF_8: u8 = 0b1000_0000;
-    const DIFF_16: u8 = 0b1100_0000;
-    const DIFF_24: u8 = 0b1110_0000;
-    const COLOR: u8 = 0b1111_0000;
+    /// The tag of each opcode, which is in the bits of the first byte covered
+    /// by the matching `MASK_*` constant. The remaining bits hold operands.
+    ///
+    /// `INDEX` and `DIFF_8` use [`Qoi::MASK_2`], `RUN_8`, `RUN_16` and
+    /// `DIFF_16` use [`Qoi::MASK_3`], and `DIFF_24` and `COLOR` use
+    /// [`Qoi::MASK_4`].
+    pub const INDEX: u8 = 0;
 
-    const MASK_2: u8 = 0b1100_0000;
-    const MASK_3: u8 = 0b1110_0000;
-    const MASK_4: u8 = 0b1111_0000;
+    pub const RUN_8: u8 = 0b0100_0000;
+    pub const RUN_16: u8 = 0b0110_0000;
+    pub const DIFF_8: u8 = 0b1000_0000;
+    pub const DIFF_16: u8 = 0b1100_0000;
+    pub const DIFF_24: u8 = 0b1110_0000;
+    pub const COLOR: u8 = 0b1111_0000;
+
+    /// Masks for the tag in the first byte of an opcode, see [`Qoi::INDEX`].
+    pub const MASK_2: u8 = 0b1100_0000;
+    pub const MASK_3: u8 = 0b1110_0000;
+    pub const MASK_4: u8 = 0b1111_0000;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub struct QoiHeader {
     width: u32,
     height: u32,
     channels: Channels,
-    colour_space: u8,
+    colour_space: ColourSpace,
 }
 
 impl QoiHeader {
-    pub fn new(width: u32, height: u32, channels: Channels, colour_space: u8) -> Self {
+    pub fn new(width: u32, height: u32, channels: Channels, colour_space: ColourSpace) -> Self {
         Self {
             width,
             height,
@@ -167,18 +568,29 @@ impl QoiHeader {
         }
     }
 
-    fn to_array(&self) -> [u8; Qoi::HEADER_SIZE] {
+    /// Creates a builder for a 4 channel sRGB header. The width and height
+    /// must be set before building.
+    pub fn builder() -> QoiHeaderBuilder {
+        QoiHeaderBuilder::default()
+    }
+
+    fn to_array(self) -> [u8; Qoi::HEADER_SIZE] {
         let mut dest = [0u8; Qoi::HEADER_SIZE];
 
         dest[0..4].copy_from_slice(b"qoif");
         dest[4..8].copy_from_slice(&self.width.to_be_bytes());
         dest[8..12].copy_from_slice(&self.height.to_be_bytes());
         dest[12] = self.channels.len();
-        dest[13] = self.colour_space;
+        dest[13] = self.colour_space.into();
 
         dest
     }
 
+    /// The header as it's written at the start of an encoded image.
+    pub fn as_bytes(&self) -> [u8; Qoi::HEADER_SIZE] {
+        self.to_array()
+    }
+
     pub fn width(&self) -> u32 {
         self.width
     }
@@ -187,24 +599,110 @@ impl QoiHeader {
         self.height
     }
 
-    /// The size of the image in its raw, uncompressed format.
-    pub fn raw_image_size(&self, channels: Channels) -> usize {
-        let width = self.width as usize;
-        let height = self.height as usize;
-        let channels = channels.len() as usize;
-        width.saturating_mul(height).saturating_mul(channels)
+    pub fn set_width(&mut self, width: u32) {
+        self.width = width;
+    }
+
+    pub fn set_height(&mut self, height: u32) {
+        self.height = height;
+    }
+
+    /// Overwrites the header at the start of the encoded image in `buffer`
+    /// with this one, without re-encoding it. This is only useful for
+    /// correcting metadata, such as transposed dimensions, as the opcodes
+    /// must still decode to `width * height` pixels.
+    pub fn rewrite_in(&self, buffer: &mut [u8]) -> Result<(), QoiError> {
+        let existing = buffer
+            .get_mut(..Qoi::HEADER_SIZE)
+            .ok_or(QoiError::InputSmallerThanHeader)?;
+
+        if &existing[0..4] != b"qoif" {
+            return Err(QoiError::IncorrectHeaderMagic);
+        }
+
+        existing.copy_from_slice(&self.to_array());
+        Ok(())
+    }
+
+    /// The size of the image in its raw, uncompressed format, or
+    /// [`QoiError::TooBig`] if that overflows a `usize`.
+    pub fn raw_image_size(&self, channels: Channels) -> Result<usize, QoiError> {
+        (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|pixels| pixels.checked_mul(channels.len() as usize))
+            .ok_or(QoiError::TooBig {
+                requested: usize::MAX,
+                max: usize::MAX,
+            })
+    }
+
+    /// The largest size the image could be encoded to, where every pixel is a
+    /// COLOR command containing each channel.
+    pub fn max_encoded_size(&self) -> usize {
+        (self.width as usize)
+            .saturating_mul(self.height as usize)
+            .saturating_mul(self.channels.len() as usize + 1)
+            .saturating_add(Qoi::HEADER_SIZE)
+            .saturating_add(Qoi::PADDING_SIZE as usize)
+    }
+
+    /// Whether the image's worst case encoded size, as returned by
+    /// [`QoiHeader::max_encoded_size`], is within [`Qoi::MAX_SIZE`]. Images
+    /// which don't fit are rejected with [`QoiError::TooBig`].
+    pub fn fits(&self) -> bool {
+        (self.width as usize)
+            .checked_mul(self.height as usize)
+            .and_then(|pixels| pixels.checked_mul(self.channels.len() as usize + 1))
+            .and_then(|size| size.checked_add(Qoi::HEADER_SIZE + Qoi::PADDING_SIZE as usize))
+            .is_some_and(|size| size <= Qoi::MAX_SIZE)
+    }
+
+    /// The size of the image in its raw format when using `channels`, or
+    /// [`QoiError::TooBig`] if that exceeds the maximum supported size.
+    #[cfg(feature = "alloc")]
+    fn checked_raw_image_size(&self, channels: Channels) -> Result<usize, QoiError> {
+        let size = self.raw_image_size(channels)?;
+        if size > Qoi::MAX_SIZE {
+            return Err(QoiError::TooBig {
+                requested: size,
+                max: Qoi::MAX_SIZE,
+            });
+        }
+        Ok(size)
+    }
+
+    /// The size of one row in its raw format when using `channels`, or
+    /// [`QoiError::TooBig`] if that exceeds the maximum supported size.
+    #[cfg(feature = "alloc")]
+    fn checked_row_size(&self, channels: Channels) -> Result<usize, QoiError> {
+        let size = (self.width as usize).saturating_mul(channels.len() as usize);
+        if size > Qoi::MAX_SIZE {
+            return Err(QoiError::TooBig {
+                requested: size,
+                max: Qoi::MAX_SIZE,
+            });
+        }
+        Ok(size)
     }
 
     pub fn channels(&self) -> Channels {
         self.channels
     }
 
-    pub fn colour_space(&self) -> u8 {
+    pub fn colour_space(&self) -> ColourSpace {
         self.colour_space
     }
 
+    /// Returns [`QoiError::EmptyImage`] if either dimension is zero.
+    fn check_dimensions(&self) -> Result<(), QoiError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(QoiError::EmptyImage);
+        }
+        Ok(())
+    }
+
     fn new_from_slice(input: &[u8]) -> Result<Self, QoiError> {
-        if input.len() < Qoi::HEADER_SIZE as usize {
+        if input.len() < Qoi::HEADER_SIZE {
             return Err(QoiError::InputSmallerThanHeader);
         }
 
@@ -216,34 +714,120 @@ impl QoiHeader {
             width: u32::from_be_bytes(input[4..8].try_into().unwrap()),
             height: u32::from_be_bytes(input[8..12].try_into().unwrap()),
             channels: input[12].try_into()?,
-            colour_space: input[13],
+            colour_space: input[13].try_into()?,
+        };
+        header.check_dimensions()?;
+
+        Ok(header)
+    }
+}
+
+impl Display for QoiHeader {
+    /// Formats the header as, for example, `QOI 1920x1080 RGBA srgb`.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let channels = match self.channels {
+            Channels::Three => "RGB",
+            Channels::Four => "RGBA",
+        };
+        let colour_space = match self.colour_space {
+            ColourSpace::Srgb => "srgb",
+            ColourSpace::SrgbLinearAlpha => "srgb-linear-alpha",
+            ColourSpace::Linear => "linear",
         };
 
+        write!(
+            f,
+            "QOI {}x{} {} {}",
+            self.width, self.height, channels, colour_space
+        )
+    }
+}
+
+/// Builds a [`QoiHeader`], from [`QoiHeader::builder`].
+#[derive(Debug, Clone, Copy)]
+pub struct QoiHeaderBuilder {
+    width: u32,
+    height: u32,
+    channels: Channels,
+    colour_space: ColourSpace,
+}
+
+impl Default for QoiHeaderBuilder {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            channels: Channels::Four,
+            colour_space: ColourSpace::Srgb,
+        }
+    }
+}
+
+impl QoiHeaderBuilder {
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn channels(mut self, channels: Channels) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    pub fn colour_space(mut self, colour_space: ColourSpace) -> Self {
+        self.colour_space = colour_space;
+        self
+    }
+
+    /// Returns [`QoiError::EmptyImage`] if the width or height is zero.
+    pub fn build(self) -> Result<QoiHeader, QoiError> {
+        let header = QoiHeader::new(self.width, self.height, self.channels, self.colour_space);
+        header.check_dimensions()?;
         Ok(header)
     }
 }
 
-pub(crate) struct FallibleReader<'a> {
+/// Reads bytes from a slice, returning [`QoiError::InputSize`] instead of
+/// panicking when reading past its end.
+pub struct FallibleReader<'a> {
     buf: &'a [u8],
     pos: usize,
 }
 
 impl<'a> FallibleReader<'a> {
     #[inline(always)]
-    fn new(buf: &'a [u8]) -> Self {
+    pub fn new(buf: &'a [u8]) -> Self {
         Self { buf, pos: 0 }
     }
 
+    /// The number of bytes read so far.
     #[inline(always)]
-    fn read(&mut self) -> Result<u8, QoiError> {
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Reads the next byte.
+    #[inline(always)]
+    pub fn read(&mut self) -> Result<u8, QoiError> {
         let value = *self.buf.get(self.pos).ok_or(QoiError::InputSize)?;
         self.pos += 1;
         Ok(value)
     }
 
+    /// Reads the next `length` bytes. Nothing is read if there are fewer
+    /// than `length` bytes left.
     #[inline(always)]
-    fn read_slice(&mut self, length: usize) -> Result<&[u8], QoiError> {
-        if self.buf.len() > self.pos + length {
+    pub fn read_slice(&mut self, length: usize) -> Result<&[u8], QoiError> {
+        if self
+            .pos
+            .checked_add(length)
+            .is_some_and(|end| end <= self.buf.len())
+        {
             let slice = &self.buf[self.pos..self.pos + length];
             self.pos += length;
             Ok(slice)
@@ -253,34 +837,94 @@ impl<'a> FallibleReader<'a> {
     }
 }
 
-pub(crate) struct FallibleWriter<'a> {
+/// A source of encoded bytes.
+pub(crate) trait Source {
+    fn read(&mut self) -> Result<u8, QoiError>;
+
+    /// The number of bytes read so far.
+    fn pos(&self) -> usize;
+}
+
+impl<'a> Source for FallibleReader<'a> {
+    #[inline(always)]
+    fn read(&mut self) -> Result<u8, QoiError> {
+        FallibleReader::read(self)
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+/// A destination for encoded bytes.
+pub(crate) trait Sink {
+    fn write(&mut self, value: u8) -> Result<(), QoiError>;
+    fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError>;
+
+    /// The number of bytes written so far.
+    fn pos(&self) -> usize;
+}
+
+/// Returns the first `len` bytes of `dest`, or [`QoiError::OutputTooSmall`] if
+/// it's shorter than that.
+pub(crate) fn output_prefix(dest: &mut [u8], len: usize) -> Result<&mut [u8], QoiError> {
+    let got = dest.len();
+    dest.get_mut(..len)
+        .ok_or(QoiError::OutputTooSmall { needed: len, got })
+}
+
+/// Writes bytes to a slice, returning [`QoiError::OutputTooSmall`] instead of
+/// panicking when writing past its end.
+pub struct FallibleWriter<'a> {
     buf: &'a mut [u8],
     pos: usize,
 }
 
 impl<'a> FallibleWriter<'a> {
     #[inline(always)]
-    fn new(buf: &'a mut [u8]) -> Self {
+    pub fn new(buf: &'a mut [u8]) -> Self {
         Self { buf, pos: 0 }
     }
 
+    /// The number of bytes written so far.
     #[inline(always)]
-    fn write(&mut self, value: u8) -> Result<(), QoiError> {
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Writes `value` at the current position and advances past it.
+    #[inline(always)]
+    pub fn write(&mut self, value: u8) -> Result<(), QoiError> {
         self.write_at(self.pos, value)?;
         self.pos += 1;
         Ok(())
     }
 
+    /// Writes `value` at `pos` without changing the current position.
     #[inline(always)]
-    fn write_at(&mut self, pos: usize, value: u8) -> Result<(), QoiError> {
-        *(self.buf.get_mut(pos).ok_or(QoiError::OutputTooSmall)?) = value;
+    pub fn write_at(&mut self, pos: usize, value: u8) -> Result<(), QoiError> {
+        let got = self.buf.len();
+        *(self.buf.get_mut(pos).ok_or(QoiError::OutputTooSmall {
+            needed: pos + 1,
+            got,
+        })?) = value;
         Ok(())
     }
 
+    /// Writes `slice` at the current position and advances past it. The
+    /// position isn't advanced if it doesn't fit, but the bytes which do fit
+    /// are written.
     #[inline]
-    fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError> {
+    pub fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError> {
+        let needed = self.pos + slice.len();
+
         for (index, v) in slice.iter().enumerate() {
-            self.write_at(self.pos + index, *v)?;
+            self.write_at(self.pos + index, *v)
+                .map_err(|_| QoiError::OutputTooSmall {
+                    needed,
+                    got: self.buf.len(),
+                })?;
         }
 
         self.pos += slice.len();
@@ -289,6 +933,23 @@ impl<'a> FallibleWriter<'a> {
     }
 }
 
+impl<'a> Sink for FallibleWriter<'a> {
+    #[inline(always)]
+    fn write(&mut self, value: u8) -> Result<(), QoiError> {
+        FallibleWriter::write(self, value)
+    }
+
+    #[inline(always)]
+    fn write_slice(&mut self, slice: &[u8]) -> Result<(), QoiError> {
+        FallibleWriter::write_slice(self, slice)
+    }
+
+    #[inline(always)]
+    fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -309,4 +970,64 @@ mod test {
         pixel.modify_a(7);
         assert_eq!(pixel, Pixel::new(1, 1, 1, 1));
     }
+
+    #[test]
+    fn pixel_luminance() {
+        assert_eq!(Pixel::new(0, 0, 0, 0).luminance(), 0);
+        assert_eq!(Pixel::new(255, 255, 255, 0).luminance(), 255);
+        assert_eq!(Pixel::new(255, 0, 0, 255).luminance(), 76);
+        assert_eq!(Pixel::new(0, 255, 0, 255).luminance(), 150);
+        assert_eq!(Pixel::new(0, 0, 255, 255).luminance(), 29);
+    }
+
+    #[test]
+    fn header_fits() {
+        let pixels = Qoi::MAX_PIXELS as u32;
+        assert!(QoiHeader::new(pixels, 1, Channels::Four, ColourSpace::Srgb).fits());
+        assert!(!QoiHeader::new(pixels + 1, 1, Channels::Four, ColourSpace::Srgb).fits());
+        assert!(QoiHeader::new(pixels + 1, 1, Channels::Three, ColourSpace::Srgb).fits());
+        assert!(!QoiHeader::new(u32::MAX, u32::MAX, Channels::Three, ColourSpace::Srgb).fits());
+    }
+
+    #[test]
+    fn raw_image_size_overflow() {
+        let header = QoiHeader::new(u32::MAX, u32::MAX, Channels::Four, ColourSpace::Srgb);
+        assert_eq!(
+            header.raw_image_size(Channels::Four),
+            Err(QoiError::TooBig {
+                requested: usize::MAX,
+                max: usize::MAX
+            })
+        );
+
+        // Only overflows when usize is 32 bits.
+        let header = QoiHeader::new(0x1_0000, 0x1_0000, Channels::Four, ColourSpace::Srgb);
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(
+            header.raw_image_size(Channels::Four),
+            Err(QoiError::TooBig {
+                requested: usize::MAX,
+                max: usize::MAX
+            })
+        );
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(header.raw_image_size(Channels::Four), Ok(1 << 34));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn size_limit_uses_requested_channels() {
+        let header = QoiHeader::new(16384, 16385, Channels::Four, ColourSpace::Srgb);
+        assert_eq!(
+            header.checked_raw_image_size(Channels::Three).unwrap(),
+            16384 * 16385 * 3
+        );
+        assert_eq!(
+            header.checked_raw_image_size(Channels::Four),
+            Err(QoiError::TooBig {
+                requested: 16384 * 16385 * 4,
+                max: Qoi::MAX_SIZE
+            })
+        );
+    }
 }
@@ -0,0 +1,142 @@
+//! A container of independently encoded horizontal stripes, so that encoding
+//! and decoding can be spread across threads.
+//!
+//! This isn't a standard QOI stream and can't be read by other decoders. The
+//! layout is:
+//!
+//! - A 14 byte header laid out like a QOI header, with the magic `qoip`.
+//! - The number of stripes as a big endian `u32`.
+//! - The offset of each stripe from the start of the container as a big
+//!   endian `u64`.
+//! - The stripes, each a complete QOI image with the full width. Every stripe
+//!   has the same height except the last, which may be shorter.
+
+use crate::{Channels, Qoi, QoiDecode, QoiEncode, QoiError, QoiHeader};
+use alloc::{vec, vec::Vec};
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+const MAGIC: &[u8; 4] = b"qoip";
+
+pub(crate) fn encode_parallel(raw: &[u8], header: &QoiHeader) -> Result<Vec<u8>, QoiError> {
+    header.check_dimensions()?;
+    let raw_image_size = header.checked_raw_image_size(header.channels)?;
+    let raw = raw.get(..raw_image_size).ok_or(QoiError::InputSize)?;
+
+    let threads = rayon::current_num_threads() as u32;
+    let rows = header.height.div_ceil(threads.clamp(1, header.height));
+    let row_size = header.width as usize * header.channels.len() as usize;
+
+    let stripes = raw
+        .par_chunks(rows as usize * row_size)
+        .map(|stripe| {
+            let height = (stripe.len() / row_size) as u32;
+            stripe.qoi_encode_to_vec(header.width, height, header.channels, header.colour_space)
+        })
+        .collect::<Result<Vec<_>, QoiError>>()?;
+
+    let table_size = 4 + stripes.len() * 8;
+    let size = Qoi::HEADER_SIZE + table_size + stripes.iter().map(Vec::len).sum::<usize>();
+    let mut output = Vec::with_capacity(size);
+
+    output.extend_from_slice(MAGIC);
+    output.extend_from_slice(&header.to_array()[4..]);
+    output.extend_from_slice(&(stripes.len() as u32).to_be_bytes());
+
+    let mut offset = Qoi::HEADER_SIZE + table_size;
+    for stripe in &stripes {
+        output.extend_from_slice(&(offset as u64).to_be_bytes());
+        offset += stripe.len();
+    }
+
+    for stripe in &stripes {
+        output.extend_from_slice(stripe);
+    }
+
+    Ok(output)
+}
+
+pub(crate) fn decode_parallel(
+    input: &[u8],
+    channels: Option<Channels>,
+) -> Result<(QoiHeader, Vec<u8>), QoiError> {
+    let mut header = [0; Qoi::HEADER_SIZE];
+    header.copy_from_slice(
+        input
+            .get(..Qoi::HEADER_SIZE)
+            .ok_or(QoiError::InputSmallerThanHeader)?,
+    );
+
+    if &header[..4] != MAGIC {
+        return Err(QoiError::IncorrectHeaderMagic);
+    }
+
+    header[..4].copy_from_slice(b"qoif");
+    let header = QoiHeader::new_from_slice(&header)?;
+    let channels = channels.unwrap_or(header.channels);
+
+    let count = input
+        .get(Qoi::HEADER_SIZE..Qoi::HEADER_SIZE + 4)
+        .ok_or(QoiError::InvalidHeader)?;
+    let count = u32::from_be_bytes(count.try_into().unwrap());
+
+    if count == 0 || count > header.height {
+        return Err(QoiError::InvalidHeader);
+    }
+
+    let table_start = Qoi::HEADER_SIZE + 4;
+    let table = input
+        .get(table_start..table_start + count as usize * 8)
+        .ok_or(QoiError::InvalidHeader)?;
+
+    let mut offsets = table
+        .chunks_exact(8)
+        .map(|offset| u64::from_be_bytes(offset.try_into().unwrap()) as usize)
+        .collect::<Vec<_>>();
+    offsets.push(input.len());
+
+    if offsets[0] != table_start + table.len() || offsets.windows(2).any(|w| w[0] > w[1]) {
+        return Err(QoiError::InvalidHeader);
+    }
+
+    let rows = header.height.div_ceil(count);
+    if header.height.div_ceil(rows) != count {
+        return Err(QoiError::InvalidHeader);
+    }
+
+    let row_size = header.width as usize * channels.len() as usize;
+    let mut output = vec![0; header.checked_raw_image_size(channels)?];
+
+    output
+        .par_chunks_mut(rows as usize * row_size)
+        .zip(offsets.par_windows(2))
+        .try_for_each(|(dest, range)| {
+            let stripe = &input[range[0]..range[1]];
+            let stripe_header = QoiHeader::new_from_slice(stripe)?;
+
+            if stripe_header.width != header.width
+                || stripe_header.height as usize * row_size != dest.len()
+            {
+                return Err(QoiError::InvalidHeader);
+            }
+
+            stripe.qoi_decode(Some(channels), dest)
+        })?;
+
+    Ok((header, output))
+}
+
+/// Reads and validates each file with [`QoiDecode::qoi_validate`] in
+/// parallel, returning the results in the same order as `paths`. Files which
+/// can't be read report [`QoiError::Io`].
+pub fn qoi_validate_all(paths: &[PathBuf]) -> Vec<(PathBuf, Result<QoiHeader, QoiError>)> {
+    paths
+        .par_iter()
+        .map(|path| {
+            let result = std::fs::read(path)
+                .map_err(QoiError::from)
+                .and_then(|input| input.qoi_validate());
+            (path.clone(), result)
+        })
+        .collect()
+}
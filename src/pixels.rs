@@ -0,0 +1,55 @@
+use crate::{decode::PixelDecoder, QoiError, QoiHeader};
+
+/// An iterator lazily decoding each pixel as an `(r, g, b, a)` tuple.
+///
+/// Exactly `width * height` pixels are produced unless decoding fails, in
+/// which case the error is produced once and ends the iteration.
+pub struct QoiPixels<'a> {
+    decoder: PixelDecoder<'a>,
+    header: QoiHeader,
+    remaining: usize,
+}
+
+impl<'a> QoiPixels<'a> {
+    pub fn new(input: &'a [u8]) -> Result<Self, QoiError> {
+        let (header, decoder) = PixelDecoder::new(input)?;
+        let remaining = (header.width as usize).saturating_mul(header.height as usize);
+
+        Ok(Self {
+            decoder,
+            header,
+            remaining,
+        })
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+}
+
+impl<'a> Iterator for QoiPixels<'a> {
+    type Item = Result<(u8, u8, u8, u8), QoiError>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match self.decoder.next_pixel() {
+            Ok(pixel) => {
+                self.remaining -= 1;
+                Some(Ok((pixel.r, pixel.g, pixel.b, pixel.a)))
+            }
+            Err(error) => {
+                self.remaining = 0;
+                Some(Err(error))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // An error takes the place of the remaining pixels.
+        (self.remaining.min(1), Some(self.remaining))
+    }
+}
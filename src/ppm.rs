@@ -0,0 +1,43 @@
+use crate::{decode::write_pixel, decode::PixelDecoder, Channels, QoiError};
+use std::io::Write;
+
+/// Decodes `input` as a binary P6 PPM for 3 channel images, or a P7 PAM for 4
+/// channel images.
+pub(crate) fn decode_to_ppm(input: &[u8], out: &mut impl Write) -> Result<(), QoiError> {
+    let (header, mut decoder) = PixelDecoder::new(input)?;
+    let channels = header.channels;
+    let mut row = vec![0; header.checked_row_size(channels)?];
+
+    match channels {
+        Channels::Three => write!(out, "P6\n{} {}\n255\n", header.width, header.height)?,
+        Channels::Four => write!(
+            out,
+            "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+            header.width, header.height
+        )?,
+    }
+
+    for _ in 0..header.height {
+        for chunk in row.chunks_exact_mut(channels.len() as usize) {
+            write_pixel(chunk, decoder.next_pixel()?, channels)?;
+        }
+
+        out.write_all(&row)?;
+    }
+
+    Ok(())
+}
+
+/// Decodes `input` to RGB behind a binary P6 PPM header, dropping any alpha.
+pub(crate) fn decode_to_ppm_vec(input: &[u8]) -> Result<Vec<u8>, QoiError> {
+    let (header, mut decoder) = PixelDecoder::new(input)?;
+    let mut out = format!("P6\n{} {}\n255\n", header.width, header.height).into_bytes();
+    let start = out.len();
+    out.resize(start + header.checked_raw_image_size(Channels::Three)?, 0);
+
+    for chunk in out[start..].chunks_exact_mut(3) {
+        write_pixel(chunk, decoder.next_pixel()?, Channels::Three)?;
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,102 @@
+use crate::{decode::write_pixel, decode::PixelDecoder, Channels, QoiError, QoiHeader};
+use alloc::{vec, vec::Vec};
+use core::ops::Range;
+
+/// Decodes an image one row at a time into a ring buffer holding only the
+/// most recently decoded rows, so the decoded output is bounded by
+/// `ring_rows * width * channels` regardless of the image height.
+///
+/// The encoded image is read from a slice, so it must still be held in memory
+/// in full. Use [`QoiStreamDecoder`](crate::QoiStreamDecoder) with a buffer
+/// of your own to also avoid buffering the encoded input.
+pub struct QoiRingDecoder<'a> {
+    decoder: PixelDecoder<'a>,
+    header: QoiHeader,
+    channels: Channels,
+    ring: Vec<u8>,
+    ring_rows: u32,
+    decoded_rows: u32,
+}
+
+impl<'a> QoiRingDecoder<'a> {
+    /// Returns [`QoiError::EmptyImage`] if `ring_rows` is zero. Using more
+    /// rows than the image contains doesn't allocate more than is needed to
+    /// hold the whole image.
+    pub fn new(
+        input: &'a [u8],
+        channels: Option<Channels>,
+        ring_rows: u32,
+    ) -> Result<Self, QoiError> {
+        let (header, decoder) = PixelDecoder::new(input)?;
+        let channels = channels.unwrap_or(header.channels);
+
+        if ring_rows == 0 {
+            return Err(QoiError::EmptyImage);
+        }
+
+        let ring_rows = ring_rows.min(header.height);
+        let ring_size = QoiHeader::new(header.width, ring_rows, channels, header.colour_space)
+            .checked_raw_image_size(channels)?;
+
+        Ok(Self {
+            decoder,
+            header,
+            channels,
+            ring: vec![0; ring_size],
+            ring_rows,
+            decoded_rows: 0,
+        })
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+
+    fn row_size(&self) -> usize {
+        self.header.width as usize * self.channels.len() as usize
+    }
+
+    fn ring_range(&self, y: u32) -> Range<usize> {
+        let start = (y % self.ring_rows) as usize * self.row_size();
+        start..start + self.row_size()
+    }
+
+    /// Decodes the next row into the ring, overwriting the oldest row if the
+    /// ring is full. Returns `None` once every row has been decoded.
+    pub fn decode_row(&mut self) -> Result<Option<&[u8]>, QoiError> {
+        if self.decoded_rows == self.header.height {
+            return Ok(None);
+        }
+
+        let y = self.decoded_rows;
+        let range = self.ring_range(y);
+        let channels = self.channels;
+
+        for chunk in self.ring[range.clone()].chunks_exact_mut(channels.len() as usize) {
+            write_pixel(chunk, self.decoder.next_pixel()?, channels)?;
+        }
+
+        self.decoded_rows += 1;
+
+        Ok(Some(&self.ring[range]))
+    }
+
+    /// The image rows currently held in the ring.
+    pub fn valid_rows(&self) -> Range<u32> {
+        self.decoded_rows.saturating_sub(self.ring_rows)..self.decoded_rows
+    }
+
+    /// The decoded image row `y`, if it's still held in the ring.
+    pub fn row(&self, y: u32) -> Option<&[u8]> {
+        if self.valid_rows().contains(&y) {
+            Some(&self.ring[self.ring_range(y)])
+        } else {
+            None
+        }
+    }
+
+    /// The rows currently held in the ring, oldest first.
+    pub fn window(&self) -> impl Iterator<Item = &[u8]> {
+        self.valid_rows().filter_map(|y| self.row(y))
+    }
+}
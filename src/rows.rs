@@ -0,0 +1,80 @@
+use crate::{decode::write_pixel, decode::PixelDecoder, Channels, QoiError, QoiHeader};
+use alloc::{vec, vec::Vec};
+
+/// Decodes one row at a time into a single reused buffer.
+///
+/// [`QoiRows::next_row`] lends out the buffer without copying. As an
+/// [`Iterator`] can't lend out its internal state, iterating copies each row
+/// into its own buffer instead. The first error ends the iteration.
+pub struct QoiRows<'a> {
+    decoder: PixelDecoder<'a>,
+    header: QoiHeader,
+    channels: Channels,
+    row: Vec<u8>,
+    decoded_rows: u32,
+    failed: bool,
+}
+
+impl<'a> QoiRows<'a> {
+    pub fn new(input: &'a [u8], channels: Option<Channels>) -> Result<Self, QoiError> {
+        let (header, decoder) = PixelDecoder::new(input)?;
+        let channels = channels.unwrap_or(header.channels);
+        let row = vec![0; header.checked_row_size(channels)?];
+
+        Ok(Self {
+            decoder,
+            header,
+            channels,
+            row,
+            decoded_rows: 0,
+            failed: false,
+        })
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+
+    /// Decodes the next row into the internal buffer and returns it, or
+    /// `None` once every row has been decoded or an error was returned.
+    pub fn next_row(&mut self) -> Option<Result<&[u8], QoiError>> {
+        if self.failed || self.decoded_rows == self.header.height {
+            return None;
+        }
+
+        self.decoded_rows += 1;
+        if let Err(error) = self.decode_row() {
+            self.failed = true;
+            return Some(Err(error));
+        }
+
+        Some(Ok(&self.row))
+    }
+
+    fn decode_row(&mut self) -> Result<(), QoiError> {
+        let channels = self.channels;
+
+        for chunk in self.row.chunks_exact_mut(channels.len() as usize) {
+            write_pixel(chunk, self.decoder.next_pixel()?, channels)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for QoiRows<'a> {
+    type Item = Result<Vec<u8>, QoiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_row().map(|row| row.map(<[u8]>::to_vec))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.failed {
+            return (0, Some(0));
+        }
+
+        let remaining = (self.header.height - self.decoded_rows) as usize;
+        (0, Some(remaining))
+    }
+}
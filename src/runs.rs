@@ -0,0 +1,58 @@
+use crate::{decode::PixelDecoder, Pixel, QoiError, QoiHeader};
+
+/// An iterator lazily decoding each opcode as a pixel and the number of
+/// times it's repeated, without expanding runs.
+///
+/// The counts add up to `width * height` unless decoding fails, in which
+/// case the error is produced once and ends the iteration. Consecutive items may have the same pixel, for
+/// example when a run is followed by an INDEX of the same pixel.
+pub struct QoiRuns<'a> {
+    decoder: PixelDecoder<'a>,
+    header: QoiHeader,
+    remaining: usize,
+}
+
+impl<'a> QoiRuns<'a> {
+    pub fn new(input: &'a [u8]) -> Result<Self, QoiError> {
+        let (header, decoder) = PixelDecoder::new(input)?;
+        let remaining = (header.width as usize).saturating_mul(header.height as usize);
+
+        Ok(Self {
+            decoder,
+            header,
+            remaining,
+        })
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+}
+
+impl<'a> Iterator for QoiRuns<'a> {
+    type Item = Result<(Pixel, usize), QoiError>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        match self.decoder.next_run() {
+            Ok((pixel, count)) => {
+                let count = count.min(self.remaining);
+                self.remaining -= count;
+                Some(Ok((pixel, count)))
+            }
+            Err(error) => {
+                self.remaining = 0;
+                Some(Err(error))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // A run may cover every remaining pixel.
+        (self.remaining.min(1), Some(self.remaining))
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{Channels, ColourSpace};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+impl Serialize for Channels {
+    /// Serializes as the number of channels.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.len())
+    }
+}
+
+impl<'de> Deserialize<'de> for Channels {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Channels::try_from(u8::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for ColourSpace {
+    /// Serializes as the header's colour space byte.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8((*self).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for ColourSpace {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        ColourSpace::try_from(u8::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
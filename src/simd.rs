@@ -0,0 +1,81 @@
+//! Scans ahead for the end of runs when encoding, comparing 48 bytes at a
+//! time. 48 bytes holds a whole number of both 3 and 4 channel pixels.
+
+use crate::{
+    encode::{read_pixel, PixelEncoder},
+    ChannelOrder, Channels, QoiError, Sink,
+};
+use wide::u8x16;
+
+const BLOCK_SIZE: usize = 48;
+
+/// Returns how many whole pixels at the start of `src` have the same bytes as
+/// `pixel`.
+fn run_length(src: &[u8], pixel: &[u8]) -> usize {
+    let pixel_size = pixel.len();
+
+    let mut pattern = [0; BLOCK_SIZE];
+    for chunk in pattern.chunks_exact_mut(pixel_size) {
+        chunk.copy_from_slice(pixel);
+    }
+
+    let lanes = [
+        u8x16::new(pattern[..16].try_into().unwrap()),
+        u8x16::new(pattern[16..32].try_into().unwrap()),
+        u8x16::new(pattern[32..].try_into().unwrap()),
+    ];
+
+    let mut matched = 0;
+
+    for block in src.chunks_exact(BLOCK_SIZE) {
+        let mut mask = 0u64;
+
+        for (index, lane) in lanes.iter().enumerate() {
+            let bytes = &block[index * 16..(index + 1) * 16];
+            let equal = u8x16::new(bytes.try_into().unwrap()).cmp_eq(*lane);
+            mask |= ((equal.move_mask() as u32 & 0xffff) as u64) << (index * 16);
+        }
+
+        let equal_bytes = mask.trailing_ones() as usize;
+        matched += equal_bytes - equal_bytes % pixel_size;
+
+        if equal_bytes < BLOCK_SIZE {
+            return matched / pixel_size;
+        }
+    }
+
+    // The tail is shorter than a block.
+    let tail = src[matched..]
+        .chunks_exact(pixel_size)
+        .take_while(|chunk| *chunk == pixel)
+        .count();
+
+    matched / pixel_size + tail
+}
+
+/// Encodes every pixel in `src`. Once a run has started the rest of it is
+/// found with [`run_length`] rather than comparing one pixel at a time.
+pub(crate) fn encode_pixels(
+    encoder: &mut PixelEncoder,
+    src: &[u8],
+    channels: Channels,
+    order: ChannelOrder,
+    writer: &mut impl Sink,
+) -> Result<(), QoiError> {
+    let pixel_size = channels.len() as usize;
+    let mut pos = 0;
+
+    while pos < src.len() {
+        let chunk = &src[pos..pos + pixel_size];
+        encoder.encode_pixel(read_pixel(chunk, channels, order), writer)?;
+        pos += pixel_size;
+
+        if encoder.in_run() {
+            let count = run_length(&src[pos..], chunk);
+            encoder.extend_run(count, writer)?;
+            pos += count * pixel_size;
+        }
+    }
+
+    Ok(())
+}
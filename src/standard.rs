@@ -0,0 +1,219 @@
+//! The opcodes from the final QOI specification, as written by other QOI
+//! encoders.
+//!
+//! The header and index cache are shared with the legacy format, but the
+//! index uses [`Pixel::cache_index`] and the stream ends with the
+//! [`FormatVersion::Standard`] marker. The specification only defines colour
+//! space values 0 and 1, see [`colour_space_byte`].
+
+use crate::{
+    decode::write_pixel,
+    encode::{read_pixel, worst_case_output},
+    output_prefix, ChannelOrder, Channels, ColourSpace, FallibleReader, FallibleWriter,
+    FormatVersion, Pixel, Qoi, QoiError, QoiHeader,
+};
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+
+/// The longest run a single opcode can hold, as 63 and 64 would collide with
+/// `OP_RGB` and `OP_RGBA`.
+const MAX_RUN: u8 = 62;
+
+/// The specification's colour space byte, where 0 is sRGB with linear alpha
+/// and 1 is all channels linear. Both sRGB variants are written as 0, as the
+/// specification doesn't distinguish them.
+fn colour_space_byte(colour_space: ColourSpace) -> u8 {
+    match colour_space {
+        ColourSpace::Srgb | ColourSpace::SrgbLinearAlpha => 0,
+        ColourSpace::Linear => 1,
+    }
+}
+
+/// Reverses [`colour_space_byte`], returning [`QoiError::InvalidHeader`] for
+/// values the specification doesn't define.
+fn colour_space_from_byte(value: u8) -> Result<ColourSpace, QoiError> {
+    match value {
+        0 => Ok(ColourSpace::Srgb),
+        1 => Ok(ColourSpace::Linear),
+        _ => Err(QoiError::InvalidHeader),
+    }
+}
+
+/// Reads the header of an image from the final specification.
+pub(crate) fn header(input: &[u8]) -> Result<QoiHeader, QoiError> {
+    let mut header = QoiHeader::new_from_slice(input)?;
+    header.colour_space = colour_space_from_byte(input[13])?;
+    Ok(header)
+}
+
+pub(crate) fn encode(src: &[u8], header: &QoiHeader, dest: &mut [u8]) -> Result<usize, QoiError> {
+    let mut writer = FallibleWriter::new(dest);
+    encode_to_writer(src, header, &mut writer)
+        .map_err(|error| worst_case_output(error, header, FormatVersion::Standard))?;
+    Ok(writer.pos)
+}
+
+fn encode_to_writer(
+    src: &[u8],
+    header: &QoiHeader,
+    writer: &mut FallibleWriter,
+) -> Result<(), QoiError> {
+    let channels = header.channels;
+    header.check_dimensions()?;
+
+    let raw_image_size = header.raw_image_size(channels)?;
+    let src = src.get(..raw_image_size).ok_or(QoiError::InputSize)?;
+
+    let mut header_bytes = header.to_array();
+    header_bytes[13] = colour_space_byte(header.colour_space);
+    writer.write_slice(&header_bytes)?;
+
+    let mut cache = [Pixel::default(); 64];
+    let mut previous = Pixel::new(0, 0, 0, 255);
+    let mut run = 0;
+
+    for chunk in src.chunks_exact(channels.len() as usize) {
+        let pixel = read_pixel(chunk, channels, ChannelOrder::Rgba);
+
+        if pixel == previous {
+            run += 1;
+
+            if run == MAX_RUN {
+                writer.write(OP_RUN | (run - 1))?;
+                run = 0;
+            }
+
+            continue;
+        }
+
+        if run > 0 {
+            writer.write(OP_RUN | (run - 1))?;
+            run = 0;
+        }
+
+        let index = pixel.cache_index();
+
+        if cache[index] == pixel {
+            writer.write(OP_INDEX | index as u8)?;
+        } else {
+            cache[index] = pixel;
+
+            if pixel.a == previous.a {
+                let dr = pixel.r.wrapping_sub(previous.r) as i8;
+                let dg = pixel.g.wrapping_sub(previous.g) as i8;
+                let db = pixel.b.wrapping_sub(previous.b) as i8;
+                let dr_dg = dr.wrapping_sub(dg);
+                let db_dg = db.wrapping_sub(dg);
+
+                if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                    writer.write(
+                        OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8,
+                    )?;
+                } else if (-32..=31).contains(&dg)
+                    && (-8..=7).contains(&dr_dg)
+                    && (-8..=7).contains(&db_dg)
+                {
+                    writer.write(OP_LUMA | (dg + 32) as u8)?;
+                    writer.write(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8)?;
+                } else {
+                    writer.write_slice(&[OP_RGB, pixel.r, pixel.g, pixel.b])?;
+                }
+            } else {
+                writer.write_slice(&[OP_RGBA, pixel.r, pixel.g, pixel.b, pixel.a])?;
+            }
+        }
+
+        previous = pixel;
+    }
+
+    if run > 0 {
+        writer.write(OP_RUN | (run - 1))?;
+    }
+
+    writer.write_slice(FormatVersion::Standard.end_marker())
+}
+
+pub(crate) fn decode(
+    input: &[u8],
+    channels: Option<Channels>,
+    dest: &mut [u8],
+) -> Result<(), QoiError> {
+    let header = header(input)?;
+    let channels = channels.unwrap_or(header.channels);
+
+    let end_marker = FormatVersion::Standard.end_marker();
+    if input.len() < Qoi::HEADER_SIZE + end_marker.len() {
+        return Err(QoiError::InvalidPadding);
+    }
+
+    let raw_image_size = header.raw_image_size(channels)?;
+    let dest = output_prefix(dest, raw_image_size)?;
+
+    let padding_pos = input.len() - end_marker.len();
+    let mut reader = FallibleReader::new(input);
+    reader.read_slice(Qoi::HEADER_SIZE)?;
+
+    let mut cache = [Pixel::default(); 64];
+    let mut pixel = Pixel::new(0, 0, 0, 255);
+    let mut run = 0;
+
+    for chunk in dest.chunks_exact_mut(channels.len() as usize) {
+        if run > 0 {
+            run -= 1;
+        } else if reader.pos < padding_pos {
+            let pos = reader.pos;
+            let b1 = reader.read()?;
+
+            if b1 == OP_RGB {
+                pixel.r = reader.read()?;
+                pixel.g = reader.read()?;
+                pixel.b = reader.read()?;
+            } else if b1 == OP_RGBA {
+                pixel.r = reader.read()?;
+                pixel.g = reader.read()?;
+                pixel.b = reader.read()?;
+                pixel.a = reader.read()?;
+            } else if b1 & Qoi::MASK_2 == OP_INDEX {
+                pixel = cache[b1 as usize];
+            } else if b1 & Qoi::MASK_2 == OP_DIFF {
+                pixel.modify_r(((b1 >> 4) & 0x03) as i8 - 2);
+                pixel.modify_g(((b1 >> 2) & 0x03) as i8 - 2);
+                pixel.modify_b((b1 & 0x03) as i8 - 2);
+            } else if b1 & Qoi::MASK_2 == OP_LUMA {
+                let b2 = reader.read()?;
+                let dg = (b1 & 0x3f) as i8 - 32;
+                pixel.modify_r(dg + ((b2 >> 4) & 0x0f) as i8 - 8);
+                pixel.modify_g(dg);
+                pixel.modify_b(dg + (b2 & 0x0f) as i8 - 8);
+            } else {
+                run = b1 & 0x3f;
+            }
+
+            // Without room for the marker after this opcode, the stream is
+            // either truncated or doesn't have one.
+            if reader.pos > padding_pos {
+                if !input.ends_with(end_marker) {
+                    return Err(QoiError::InvalidPadding);
+                }
+
+                return Err(QoiError::UnexpectedEnd { pos });
+            }
+
+            cache[pixel.cache_index()] = pixel;
+        }
+
+        write_pixel(chunk, pixel, channels)?;
+    }
+
+    // The marker follows the last opcode, and anything after it is ignored.
+    if input.get(reader.pos..reader.pos + end_marker.len()) != Some(end_marker) {
+        return Err(QoiError::InvalidPadding);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use crate::{Opcode, OpcodeCursor, QoiError};
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use core::fmt::Write;
+
+/// How often an opcode appears in a stream and how many bytes it takes up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeStats {
+    /// The number of times the opcode appears.
+    pub count: usize,
+    /// The total number of bytes used by the opcode, including its operands.
+    pub bytes: usize,
+}
+
+impl OpcodeStats {
+    fn record(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Per-opcode statistics for an encoded image, from
+/// [`QoiDecode::qoi_stats`](crate::QoiDecode::qoi_stats).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QoiStats {
+    pub index: OpcodeStats,
+    pub run_8: OpcodeStats,
+    pub run_16: OpcodeStats,
+    pub diff_8: OpcodeStats,
+    pub diff_16: OpcodeStats,
+    pub diff_24: OpcodeStats,
+    pub color: OpcodeStats,
+}
+
+impl QoiStats {
+    /// The total number of opcodes.
+    pub fn opcodes(&self) -> usize {
+        self.iter().map(|stats| stats.count).sum()
+    }
+
+    /// The total number of bytes used by opcodes, excluding the header and
+    /// padding.
+    pub fn bytes(&self) -> usize {
+        self.iter().map(|stats| stats.bytes).sum()
+    }
+
+    /// Formats the statistics as CSV with the columns
+    /// `opcode,count,bytes,percent`, where `percent` is the share of the
+    /// opcode bytes. There's a header row followed by one row per opcode, always
+    /// in the same order so the output can be diffed.
+    #[cfg(feature = "alloc")]
+    pub fn to_csv(&self) -> String {
+        let total = self.bytes();
+        let mut csv = String::from("opcode,count,bytes,percent\n");
+
+        for (name, stats) in self.named() {
+            let percent = if total == 0 {
+                0.0
+            } else {
+                stats.bytes as f64 * 100.0 / total as f64
+            };
+
+            // Writing to a string can't fail.
+            let _ = writeln!(csv, "{name},{},{},{percent:.2}", stats.count, stats.bytes);
+        }
+
+        csv
+    }
+
+    fn named(&self) -> [(&'static str, &OpcodeStats); 7] {
+        [
+            ("index", &self.index),
+            ("run_8", &self.run_8),
+            ("run_16", &self.run_16),
+            ("diff_8", &self.diff_8),
+            ("diff_16", &self.diff_16),
+            ("diff_24", &self.diff_24),
+            ("color", &self.color),
+        ]
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &OpcodeStats> {
+        self.named().into_iter().map(|(_, stats)| stats)
+    }
+}
+
+/// Walks the opcodes of `input` with an [`OpcodeCursor`], so opcodes are
+/// parsed and validated the same way as when decoding. Like decoding, this
+/// stops once the image is complete or the padding is reached.
+pub(crate) fn stats(input: &[u8]) -> Result<QoiStats, QoiError> {
+    let mut cursor = OpcodeCursor::new(input)?;
+    let mut stats = QoiStats::default();
+    let mut pos = cursor.pos();
+
+    while let Some((opcode, _)) = cursor.next_op()? {
+        let len = cursor.pos() - pos;
+        pos = cursor.pos();
+
+        let op = match opcode {
+            Opcode::Index(_) => &mut stats.index,
+            Opcode::Run(_) if len == 1 => &mut stats.run_8,
+            Opcode::Run(_) => &mut stats.run_16,
+            Opcode::Diff8 { .. } => &mut stats.diff_8,
+            Opcode::Diff16 { .. } => &mut stats.diff_16,
+            Opcode::Diff24 { .. } => &mut stats.diff_24,
+            Opcode::Color { .. } => &mut stats.color,
+        };
+        op.record(len);
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,53 @@
+use crate::{
+    decode::{write_pixel, PixelDecoder},
+    hash::Crc32,
+    output_prefix, Channels, QoiError,
+};
+
+/// Decodes an encoded image while computing a CRC-32 of the decoded pixels,
+/// so two decodes can be compared, or checked against a digest stored
+/// earlier, without keeping both outputs.
+pub struct VerifyingReader<'a> {
+    input: &'a [u8],
+    digest: Option<u32>,
+}
+
+impl<'a> VerifyingReader<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            digest: None,
+        }
+    }
+
+    /// Decodes the image like [`QoiDecode::qoi_decode`], hashing each pixel
+    /// as it's written to `dest`.
+    ///
+    /// [`QoiDecode::qoi_decode`]: crate::QoiDecode::qoi_decode
+    pub fn decode(
+        &mut self,
+        channels: Option<Channels>,
+        mut dest: impl AsMut<[u8]>,
+    ) -> Result<(), QoiError> {
+        self.digest = None;
+
+        let (header, mut decoder) = PixelDecoder::new(self.input)?;
+        let channels = channels.unwrap_or(header.channels);
+        let dest = output_prefix(dest.as_mut(), header.raw_image_size(channels)?)?;
+        let mut hasher = Crc32::new();
+
+        for chunk in dest.chunks_exact_mut(channels.len() as usize) {
+            write_pixel(chunk, decoder.next_pixel()?, channels)?;
+            hasher.write(chunk);
+        }
+
+        self.digest = Some(hasher.finish());
+        Ok(())
+    }
+
+    /// The CRC-32 of the pixels from the last successful
+    /// [`VerifyingReader::decode`], or `None` if there hasn't been one.
+    pub fn digest(&self) -> Option<u32> {
+        self.digest
+    }
+}
@@ -0,0 +1,54 @@
+//! Property tests encoding then decoding generated images.
+
+use proptest::prelude::*;
+use qoi::{Channels, ColourSpace, QoiDecode, QoiEncode, QoiHeader};
+
+/// An image built from a small palette, with some pixels slightly changed so
+/// that runs, index lookups, diffs and full colours are all used.
+fn image() -> impl Strategy<Value = (u32, u32, Channels, Vec<u8>)> {
+    let channels = prop_oneof![Just(Channels::Three), Just(Channels::Four)];
+    let palette = prop::collection::vec(any::<[u8; 4]>(), 1..8);
+
+    (1..=24u32, 1..=24u32, channels, palette).prop_flat_map(|(width, height, channels, palette)| {
+        let pixel = (0..palette.len(), -3i8..=3, any::<bool>());
+        let pixels = prop::collection::vec(pixel, (width * height) as usize);
+
+        pixels.prop_map(move |pixels| {
+            let mut raw = Vec::new();
+            for (index, delta, changed) in pixels {
+                let delta = if changed { delta } else { 0 };
+                let pixel = palette[index].map(|v| v.wrapping_add_signed(delta));
+                raw.extend_from_slice(&pixel[..channels.count() as usize]);
+            }
+            (width, height, channels, raw)
+        })
+    })
+}
+
+proptest! {
+    // Keep the number of cases low so normal test runs stay quick.
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn encode_decode((width, height, channels, raw) in image()) {
+        let encoded = raw
+            .qoi_encode_to_vec(width, height, channels, ColourSpace::Srgb)
+            .unwrap();
+        prop_assert!(encoded.len() <= encoded.load_qoi_header().unwrap().max_encoded_size());
+        prop_assert_eq!(encoded.qoi_decode_to_vec(None).unwrap(), raw);
+    }
+
+    #[test]
+    fn encode_decode_standard((width, height, channels, raw) in image()) {
+        let header = QoiHeader::new(width, height, channels, ColourSpace::Srgb);
+        let mut encoded = vec![0; header.max_encoded_size() + 4];
+        let len = raw
+            .qoi_encode_standard(width, height, channels, ColourSpace::Srgb, &mut encoded)
+            .unwrap();
+        encoded.truncate(len);
+
+        let mut decoded = vec![0; raw.len()];
+        encoded.qoi_decode_standard(None, &mut decoded).unwrap();
+        prop_assert_eq!(decoded, raw);
+    }
+}
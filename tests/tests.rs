@@ -1,6 +1,12 @@
-use qoi::{QoiDecode, QoiEncode, QoiError, QoiHeader};
+use qoi::{
+    AtlasReader, AtlasWriter, ChannelOrder, Channels, ColourSpace, DecodeOptions, EncodeOptions,
+    EncodeStrategy, FallibleReader, FallibleWriter, FormatVersion, HashMode, Opcode, OpcodeCursor,
+    OpcodeStats, Pixel, Qoi, QoiDecode, QoiEncode, QoiEncoder, QoiError, QoiHeader,
+    QoiStreamDecoder, QoiStreamEncoder, Transform,
+};
 use std::{
     ffi::OsStr,
+    io::Read,
     path::{Path, PathBuf},
 };
 use walkdir::WalkDir;
@@ -35,7 +41,7 @@ impl From<&Path> for TestCase {
     }
 }
 
-fn for_all_qoi_files(f: impl Fn(&TestCase)) {
+fn for_all_qoi_files(mut f: impl FnMut(&TestCase)) {
     let root = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), "images",);
 
     for entry in WalkDir::new(root).max_depth(5).into_iter() {
@@ -68,12 +74,261 @@ fn encode() {
                 case.header.width(),
                 case.header.height(),
                 case.header.channels(),
-                0,
+                case.header.colour_space(),
+            )
+            .unwrap();
+
+        // Output only differs from the reference encoder's where a wrapped
+        // DIFF_24 replaces a longer COLOR, which always makes it shorter.
+        if encoded.len() == case.encoded.len() {
+            compare_bytes(&encoded, &case.encoded);
+        } else {
+            assert!(encoded.len() < case.encoded.len());
+            let decoded = encoded.qoi_decode_to_vec(None).unwrap();
+            compare_bytes(&decoded, &case.raw);
+        }
+    });
+}
+
+#[test]
+fn encode_wrapped_diff_24() {
+    // The colour channels wrap around from the first pixel to the second.
+    let raw = [250, 250, 5, 128, 2, 3, 250, 124];
+    let encoded = raw
+        .qoi_encode_to_vec(2, 1, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    // COLOR for the first pixel, then DIFF_24 instead of a 5 byte COLOR.
+    assert_eq!(encoded.len(), 14 + 5 + 3 + 4);
+    assert_eq!(encoded[19] & 0xf0, 0xe0);
+
+    let mut decoded = [0; 8];
+    encoded.qoi_decode(None, &mut decoded).unwrap();
+    assert_eq!(decoded, raw);
+
+    // The first pixel wraps red and changes green and blue, so DIFF_24 beats
+    // a 4 byte COLOR. Then only red wraps, so a 2 byte COLOR is shorter.
+    let raw = [250, 10, 10, 255, 2, 10, 10, 255];
+    let encoded = raw
+        .qoi_encode_to_vec(2, 1, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(encoded[14..19], [0xe5, 0x6b, 0x50, 0xf8, 2]);
+    assert_eq!(encoded.len(), 14 + 3 + 2 + 4);
+}
+
+#[test]
+#[allow(deprecated)]
+fn estimate_size() {
+    for_all_qoi_files(|case| {
+        println!("Testing {}", case.path.display());
+        let header = &case.header;
+
+        let estimate = case
+            .raw
+            .qoi_estimate_size(header.width(), header.height(), header.channels())
+            .unwrap();
+        let encoded = case
+            .raw
+            .qoi_encode_to_vec(
+                header.width(),
+                header.height(),
+                header.channels(),
+                header.colour_space(),
+            )
+            .unwrap();
+        assert_eq!(estimate, encoded.len());
+        assert!(header.max_encoded_size() >= estimate);
+    });
+
+    let raw = rgba_test_image();
+    let estimate = raw.qoi_estimate_size(8, 8, Channels::Four).unwrap();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(estimate, encoded.len());
+    assert!(matches!(
+        raw.qoi_estimate_size(8, 9, Channels::Four),
+        Err(QoiError::InputSize)
+    ));
+
+    // The same image encoded as 3 channels, with another colour space.
+    let len = raw
+        .qoi_encoded_len(16, 4, Channels::Three, ColourSpace::Linear)
+        .unwrap();
+    let encoded = (&raw[..16 * 4 * 3])
+        .qoi_encode_to_vec(16, 4, Channels::Three, ColourSpace::Linear)
+        .unwrap();
+    assert_eq!(len, encoded.len());
+}
+
+#[test]
+fn weighted_hash_mode() {
+    let options = EncodeOptions {
+        hash_mode: HashMode::Weighted,
+        ..Default::default()
+    };
+    let decode_options = DecodeOptions {
+        hash_mode: HashMode::Weighted,
+        ..Default::default()
+    };
+    let mut xor_size = 0;
+    let mut weighted_size = 0;
+
+    for_all_qoi_files(|case| {
+        println!("Testing {}", case.path.display());
+        let header = &case.header;
+
+        let encoded = case
+            .raw
+            .qoi_encode_to_vec_with_options(
+                header.width(),
+                header.height(),
+                header.channels(),
+                header.colour_space(),
+                options,
             )
             .unwrap();
 
-        compare_bytes(&encoded, &case.encoded);
+        let mut decoded = vec![0; case.raw.len()];
+        encoded
+            .qoi_decode_with_options(None, &mut decoded, decode_options)
+            .unwrap();
+        compare_bytes(&decoded, &case.raw);
+
+        println!(
+            "xor: {} bytes, weighted: {} bytes",
+            case.encoded.len(),
+            encoded.len()
+        );
+        xor_size += case.encoded.len();
+        weighted_size += encoded.len();
     });
+
+    assert!(weighted_size <= xor_size);
+}
+
+#[test]
+fn channels_conversion() {
+    for channels in [Channels::Three, Channels::Four] {
+        let count = u8::from(channels);
+        assert_eq!(count, channels.count());
+        assert_eq!(Channels::try_from(count).unwrap(), channels);
+    }
+
+    assert_eq!(Channels::Three.count(), 3);
+    assert_eq!(Channels::Four.count(), 4);
+    assert_eq!(Channels::try_from(5).unwrap_err(), QoiError::Channels);
+}
+
+#[test]
+fn fallible_writer_and_reader() {
+    let mut buf = [0; 4];
+    let mut writer = FallibleWriter::new(&mut buf);
+    writer.write(1).unwrap();
+    writer.write_slice(&[2, 3]).unwrap();
+    writer.write_at(0, 4).unwrap();
+    assert_eq!(writer.pos(), 3);
+    assert_eq!(
+        writer.write_slice(&[5, 6]),
+        Err(QoiError::OutputTooSmall { needed: 5, got: 4 })
+    );
+    assert_eq!(
+        writer.write_at(4, 0),
+        Err(QoiError::OutputTooSmall { needed: 5, got: 4 })
+    );
+    writer.write(5).unwrap();
+    assert_eq!(
+        writer.write(6),
+        Err(QoiError::OutputTooSmall { needed: 5, got: 4 })
+    );
+    assert_eq!(buf, [4, 2, 3, 5]);
+
+    let mut reader = FallibleReader::new(&buf);
+    assert_eq!(reader.read(), Ok(4));
+    assert_eq!(reader.read_slice(2), Ok(&[2, 3][..]));
+    assert_eq!(reader.read_slice(2), Err(QoiError::InputSize));
+    assert_eq!(reader.read_slice(usize::MAX), Err(QoiError::InputSize));
+    assert_eq!(reader.pos(), 3);
+    assert_eq!(reader.read(), Ok(5));
+    assert_eq!(reader.read(), Err(QoiError::InputSize));
+}
+
+#[test]
+fn decode_to_image() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let image = encoded.qoi_decode_to_image(None).unwrap();
+    assert_eq!((image.width, image.height), (8, 8));
+    assert_eq!(image.channels, Channels::Four);
+    compare_bytes(&image.data, &raw);
+    assert_eq!(image.row(2), Some(&raw[64..96]));
+    assert_eq!(image.pixel(3, 2), Some(&raw[76..80]));
+    assert_eq!(image.row(8), None);
+    assert_eq!(image.pixel(8, 0), None);
+    assert_eq!(image.pixel(0, 8), None);
+
+    let image = encoded.qoi_decode_to_image(Some(Channels::Three)).unwrap();
+    assert_eq!(image.channels, Channels::Three);
+    assert_eq!(image.pixel(3, 2), Some(&raw[76..79]));
+}
+
+#[test]
+fn header_builder() {
+    let header = QoiHeader::builder().width(640).height(480).build().unwrap();
+    assert_eq!(header.width(), 640);
+    assert_eq!(header.height(), 480);
+    assert_eq!(header.channels(), Channels::Four);
+    assert_eq!(header.colour_space(), ColourSpace::Srgb);
+
+    let header = QoiHeader::builder()
+        .height(2)
+        .width(1)
+        .channels(Channels::Three)
+        .colour_space(ColourSpace::Linear)
+        .build()
+        .unwrap();
+    assert_eq!((header.width(), header.height()), (1, 2));
+    assert_eq!(header.channels(), Channels::Three);
+    assert_eq!(header.colour_space(), ColourSpace::Linear);
+
+    let error = QoiHeader::builder().width(1).build().unwrap_err();
+    assert_eq!(error, QoiError::EmptyImage);
+}
+
+#[test]
+fn header_copy_and_compare() {
+    let header = QoiHeader::new(8, 8, Channels::Four, ColourSpace::Srgb);
+    let copy = header;
+    assert_eq!(header, copy);
+
+    let encoded = rgba_test_image()
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(encoded.load_qoi_header().unwrap(), header);
+    assert_ne!(
+        header,
+        QoiHeader::new(8, 8, Channels::Three, ColourSpace::Srgb)
+    );
+}
+
+#[test]
+fn header_display_and_bytes() {
+    let header = QoiHeader::new(1920, 1080, Channels::Four, ColourSpace::Srgb);
+    assert_eq!(header.to_string(), "QOI 1920x1080 RGBA srgb");
+
+    let header = QoiHeader::new(2, 1, Channels::Three, ColourSpace::Linear);
+    assert_eq!(header.to_string(), "QOI 2x1 RGB linear");
+    assert_eq!(header.as_bytes(), *b"qoif\0\0\0\x02\0\0\0\x01\x03\x0f");
+
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let header = encoded.load_qoi_header().unwrap();
+    assert_eq!(header.as_bytes(), encoded[..14]);
 }
 
 #[test]
@@ -84,11 +339,2843 @@ fn header_magic() {
     ));
 }
 
+#[test]
+fn dimensions() {
+    let header = header_bytes(640, 480, 4);
+    assert_eq!(header.qoi_dimensions().unwrap(), (640, 480));
+    assert_eq!((&header[..12]).qoi_dimensions().unwrap(), (640, 480));
+    assert_eq!(
+        (&header[..11]).qoi_dimensions().unwrap_err(),
+        QoiError::InputSmallerThanHeader
+    );
+    assert_eq!(
+        b"boif12341234".qoi_dimensions().unwrap_err(),
+        QoiError::IncorrectHeaderMagic
+    );
+}
+
 #[test]
 fn buffer_size_errors() {
-    let mut buffer = Vec::new();
-    buffer.resize(1024, 0);
+    let mut buffer = vec![0; 1024];
 
     let error = b"qoif123412341".qoi_decode(None, &mut buffer).unwrap_err();
     assert!(matches!(error, QoiError::InputSmallerThanHeader));
+
+    // The header is read before anything else, so any input shorter than it
+    // reports the same error.
+    let header = b"qoif\0\0\0\x01\0\0\0\x01\x04\0";
+    for len in [0, 10, 13] {
+        let error = (&header[..len]).qoi_decode(None, &mut buffer).unwrap_err();
+        assert_eq!(error, QoiError::InputSmallerThanHeader, "{len} bytes");
+    }
+
+    // A complete header without any opcodes is only rejected when strict,
+    // for the missing padding.
+    header.qoi_decode(None, &mut buffer).unwrap();
+    assert_eq!(buffer[..4], [0, 0, 0, 255]);
+    let options = DecodeOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let error = header
+        .qoi_decode_with_options(None, &mut buffer, options)
+        .unwrap_err();
+    assert_eq!(error, QoiError::InvalidPadding);
+}
+
+#[test]
+fn output_too_small() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    // An exactly sized output is enough.
+    let mut exact = vec![0; encoded.len()];
+    let len = raw
+        .qoi_encode(8, 8, Channels::Four, ColourSpace::Srgb, &mut exact)
+        .unwrap();
+    assert_eq!(exact[..len], encoded);
+
+    let error = raw
+        .qoi_encode(8, 8, Channels::Four, ColourSpace::Srgb, [0; 20])
+        .unwrap_err();
+    let needed = 14 + 64 * 5 + 4;
+    assert_eq!(error, QoiError::OutputTooSmall { needed, got: 20 });
+    raw.qoi_encode(8, 8, Channels::Four, ColourSpace::Srgb, vec![0; needed])
+        .unwrap();
+
+    let error = encoded.qoi_decode_counting(None, [0; 255]).unwrap_err();
+    assert_eq!(
+        error,
+        QoiError::OutputTooSmall {
+            needed: 256,
+            got: 255
+        }
+    );
+}
+
+fn header_bytes(width: u32, height: u32, channels: u8) -> Vec<u8> {
+    let mut header = b"qoif".to_vec();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[channels, 0]);
+    header
+}
+
+#[test]
+fn diff_24_alpha_to_three_channels() {
+    // DIFF_24 to (1, 2, 3, 250), DIFF_24 to (2, 2, 3, 255), then INDEX 58,
+    // which is only the first pixel if its alpha was tracked.
+    let mut encoded = header_bytes(3, 1, 4);
+    encoded.extend_from_slice(&[0xe8, 0xca, 0x6b, 0xe8, 0xc2, 0x15, 58, 0, 0, 0, 0]);
+
+    let mut rgba = [0; 12];
+    encoded.qoi_decode(None, &mut rgba).unwrap();
+    assert_eq!(rgba, [1, 2, 3, 250, 2, 2, 3, 255, 1, 2, 3, 250]);
+
+    let mut rgb = [0; 9];
+    encoded.qoi_decode(Some(Channels::Three), &mut rgb).unwrap();
+    let dropped: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    assert_eq!(rgb[..], dropped[..]);
+}
+
+#[test]
+fn run_overflow() {
+    let strict = DecodeOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mut dest = [0; 8];
+
+    // A RUN_8 of 2 pixels fills the image exactly.
+    let mut exact = header_bytes(2, 1, 4);
+    exact.extend_from_slice(&[0x41, 0, 0, 0, 0]);
+    exact
+        .qoi_decode_with_options(None, &mut dest, strict)
+        .unwrap();
+
+    // A RUN_8 of 5 pixels, and a RUN_16 of 33 pixels.
+    for run in [&[0x44][..], &[0x60, 0x01]] {
+        let mut encoded = header_bytes(2, 1, 4);
+        encoded.extend_from_slice(run);
+        encoded.extend_from_slice(&[0, 0, 0, 0]);
+
+        encoded.qoi_decode(None, &mut dest).unwrap();
+        assert_eq!(dest, [0, 0, 0, 255, 0, 0, 0, 255]);
+        assert_eq!(
+            encoded.qoi_decode_with_options(None, &mut dest, strict),
+            Err(QoiError::RunOverflow)
+        );
+
+        let mut decoder = QoiStreamDecoder::with_options(&encoded[..], None, strict).unwrap();
+        assert_eq!(decoder.read_row(&mut dest), Err(QoiError::RunOverflow));
+    }
+}
+
+fn rgba_test_image() -> Vec<u8> {
+    let mut raw = Vec::new();
+    for i in 0..64u8 {
+        raw.extend_from_slice(&[i, i.wrapping_mul(3), 255 - i, 255 - (i % 4) * 60]);
+    }
+    raw
+}
+
+#[test]
+fn encode_strided() {
+    let raw = rgba_test_image();
+    // The image in the top left of a 10x9 frame, with rows 40 bytes apart.
+    let mut frame = vec![0xaa; 40 * 9];
+    for (y, row) in raw.chunks_exact(32).enumerate() {
+        frame[y * 40..y * 40 + 32].copy_from_slice(row);
+    }
+
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let mut encoded = vec![0; expected.len()];
+    let len = frame
+        .qoi_encode_strided(8, 8, 40, Channels::Four, ColourSpace::Srgb, &mut encoded)
+        .unwrap();
+    compare_bytes(&encoded[..len], &expected);
+
+    // The last row doesn't need its padding.
+    let len = (&frame[..40 * 7 + 32])
+        .qoi_encode_strided(8, 8, 40, Channels::Four, ColourSpace::Srgb, &mut encoded)
+        .unwrap();
+    compare_bytes(&encoded[..len], &expected);
+
+    assert_eq!(
+        (&frame[..40 * 7 + 31]).qoi_encode_strided(
+            8,
+            8,
+            40,
+            Channels::Four,
+            ColourSpace::Srgb,
+            &mut encoded
+        ),
+        Err(QoiError::InputSize)
+    );
+    assert_eq!(
+        frame.qoi_encode_strided(8, 8, 31, Channels::Four, ColourSpace::Srgb, &mut encoded),
+        Err(QoiError::InputSize)
+    );
+}
+
+#[test]
+fn decode_strided() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    // Rows 40 bytes apart, where the last row doesn't need its padding.
+    let mut dest = vec![0xaa; 40 * 7 + 32];
+    encoded.qoi_decode_strided(None, &mut dest, 40).unwrap();
+    for (y, row) in raw.chunks_exact(32).enumerate() {
+        assert_eq!(&dest[y * 40..y * 40 + 32], row);
+        if y < 7 {
+            assert_eq!(&dest[y * 40 + 32..(y + 1) * 40], &[0xaa; 8]);
+        }
+    }
+
+    // 3 channels with a stride of exactly one row.
+    let mut dest = vec![0; 8 * 8 * 3];
+    encoded
+        .qoi_decode_strided(Some(Channels::Three), &mut dest, 24)
+        .unwrap();
+    assert_eq!(
+        dest,
+        encoded.qoi_decode_to_vec(Some(Channels::Three)).unwrap()
+    );
+
+    assert_eq!(
+        encoded.qoi_decode_strided(None, &mut [0; 40 * 7 + 31], 40),
+        Err(QoiError::OutputTooSmall {
+            needed: 40 * 7 + 32,
+            got: 40 * 7 + 31
+        })
+    );
+    assert_eq!(
+        encoded.qoi_decode_strided(None, &mut [0; 1024], 31),
+        Err(QoiError::InputSize)
+    );
+}
+
+#[test]
+fn encode_growable() {
+    let raw = rgba_test_image();
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    // Starting too small to hold the header still grows to fit the image.
+    for capacity in [None, Some(0), Some(1024)] {
+        let encoded = raw
+            .qoi_encode_growable(8, 8, Channels::Four, ColourSpace::Srgb, capacity)
+            .unwrap();
+        compare_bytes(&encoded, &expected);
+    }
+
+    // A flat image only needs its default capacity.
+    let flat = vec![7; 64 * 64 * 4];
+    let encoded = flat
+        .qoi_encode_growable(64, 64, Channels::Four, ColourSpace::Srgb, None)
+        .unwrap();
+    assert_eq!(encoded.capacity(), 64 * 64 * 2 + 18);
+
+    assert_eq!(
+        (&raw[..255]).qoi_encode_growable(8, 8, Channels::Four, ColourSpace::Srgb, None),
+        Err(QoiError::InputSize)
+    );
+}
+
+#[test]
+fn gray_round_trip() {
+    let gray: Vec<u8> = (0..64u8).map(|i| i / 4 * 16).collect();
+    let rgb: Vec<u8> = gray.iter().flat_map(|&v| [v, v, v]).collect();
+
+    let mut encoded = vec![0; 512];
+    let len = gray
+        .qoi_encode_gray(8, 8, ColourSpace::Srgb, &mut encoded)
+        .unwrap();
+    let expected = rgb
+        .qoi_encode_to_vec(8, 8, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    compare_bytes(&encoded[..len], &expected);
+
+    let mut decoded = vec![0; 64];
+    (&encoded[..len]).qoi_decode_gray(&mut decoded).unwrap();
+    assert_eq!(decoded, gray);
+
+    assert_eq!(
+        (&gray[..63]).qoi_encode_gray(8, 8, ColourSpace::Srgb, &mut encoded),
+        Err(QoiError::InputSize)
+    );
+    assert_eq!(
+        (&encoded[..len]).qoi_decode_gray(&mut decoded[..63]),
+        Err(QoiError::OutputTooSmall {
+            needed: 64,
+            got: 63
+        })
+    );
+}
+
+#[test]
+fn encode_in_place() {
+    let mut raw = Vec::new();
+    for y in 0..16u8 {
+        for x in 0..16u8 {
+            raw.extend_from_slice(&[x * 4, y * 4, 128, 255]);
+        }
+    }
+    let expected = raw
+        .qoi_encode_to_vec(16, 16, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut buffer = raw.clone();
+    let len =
+        qoi::qoi_encode_in_place(&mut buffer, 16, 16, Channels::Four, ColourSpace::Srgb).unwrap();
+    compare_bytes(&buffer[..len], &expected);
+
+    // Every pixel of noise is a 4 byte COLOR opcode, which eventually catches
+    // up with the 3 byte pixels still to be read.
+    let mut state = 1u32;
+    let noise: Vec<u8> = (0..32 * 32 * 3)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    let mut buffer = noise.clone();
+    let error = qoi::qoi_encode_in_place(&mut buffer, 32, 32, Channels::Three, ColourSpace::Srgb)
+        .unwrap_err();
+    assert_eq!(error, QoiError::InPlaceOverlap);
+    assert_eq!(buffer, noise);
+
+    // A single pixel can't hold the header.
+    let mut buffer = [0, 0, 0, 255];
+    let error =
+        qoi::qoi_encode_in_place(&mut buffer, 1, 1, Channels::Four, ColourSpace::Srgb).unwrap_err();
+    assert_eq!(
+        error,
+        QoiError::OutputTooSmall {
+            needed: 14 + 1 + 4,
+            got: 4
+        }
+    );
+    assert_eq!(buffer, [0, 0, 0, 255]);
+
+    let mut buffer = raw.clone();
+    let error = qoi::qoi_encode_in_place(
+        &mut buffer[..1023],
+        16,
+        16,
+        Channels::Four,
+        ColourSpace::Srgb,
+    )
+    .unwrap_err();
+    assert_eq!(error, QoiError::InputSize);
+}
+
+#[test]
+fn decode_four_channels_to_three() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let decoded = encoded.qoi_decode_to_vec(Some(Channels::Three)).unwrap();
+
+    let expected: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    compare_bytes(&decoded, &expected);
+}
+
+#[test]
+fn decode_three_channels_to_four() {
+    let raw: Vec<u8> = rgba_test_image()
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    let decoded = encoded.qoi_decode_to_vec(Some(Channels::Four)).unwrap();
+
+    let expected: Vec<u8> = raw
+        .chunks_exact(3)
+        .flat_map(|p| [p[0], p[1], p[2], 255])
+        .collect();
+    compare_bytes(&decoded, &expected);
+}
+
+#[test]
+fn channel_conversion_matrix() {
+    let rgba = rgba_test_image();
+    let rgb: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    let opaque: Vec<u8> = rgb
+        .chunks_exact(3)
+        .flat_map(|p| [p[0], p[1], p[2], 255])
+        .collect();
+
+    let from_rgba = rgba
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let from_rgb = rgb
+        .qoi_encode_to_vec(8, 8, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+
+    let cases = [
+        (&from_rgb, Channels::Three, &rgb),
+        (&from_rgb, Channels::Four, &opaque),
+        (&from_rgba, Channels::Three, &rgb),
+        (&from_rgba, Channels::Four, &rgba),
+    ];
+
+    for (encoded, channels, expected) in cases {
+        let decoded = encoded.qoi_decode_to_vec(Some(channels)).unwrap();
+        compare_bytes(&decoded, expected);
+    }
+
+    let options = DecodeOptions {
+        deny_lossy_conversion: true,
+        ..Default::default()
+    };
+    let mut decoded = vec![0; rgb.len()];
+    let error = from_rgba
+        .qoi_decode_with_options(Some(Channels::Three), &mut decoded, options)
+        .unwrap_err();
+    assert_eq!(error, QoiError::LossyChannelConversion);
+
+    let mut decoder =
+        QoiStreamDecoder::with_options(&from_rgba[..], Some(Channels::Three), options).unwrap();
+    let mut row = [0; 24];
+    let error = loop {
+        if let Err(error) = decoder.read_row(&mut row) {
+            break error;
+        }
+    };
+    assert_eq!(error, QoiError::LossyChannelConversion);
+
+    // Opaque images can still be decoded to 3 channels.
+    let from_opaque = opaque
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    from_opaque
+        .qoi_decode_with_options(Some(Channels::Three), &mut decoded, options)
+        .unwrap();
+    compare_bytes(&decoded, &rgb);
+}
+
+#[test]
+fn standard_end_marker() {
+    let raw = rgba_test_image();
+    let legacy = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let encode_options = EncodeOptions {
+        format_version: FormatVersion::Standard,
+        ..Default::default()
+    };
+    let standard = raw
+        .qoi_encode_to_vec_with_options(8, 8, Channels::Four, ColourSpace::Srgb, encode_options)
+        .unwrap();
+
+    assert_eq!(standard.len(), legacy.len() + 4);
+    assert_eq!(&standard[standard.len() - 8..], &[0, 0, 0, 0, 0, 0, 0, 1]);
+    assert_eq!(&standard[..legacy.len() - 4], &legacy[..legacy.len() - 4]);
+
+    let options = DecodeOptions {
+        format_version: FormatVersion::Standard,
+        ..Default::default()
+    };
+    let mut decoded = vec![0; raw.len()];
+    standard
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+
+    let mut decoder = QoiStreamDecoder::with_options(&standard[..], None, options).unwrap();
+    let mut row = [0; 32];
+    while decoder.read_row(&mut row).unwrap() {}
+
+    assert_eq!(
+        legacy
+            .qoi_decode_with_options(None, &mut decoded, options)
+            .unwrap_err(),
+        QoiError::InvalidPadding
+    );
+
+    // The standard marker starts with the legacy padding, so the rest of it
+    // is treated as trailing bytes.
+    let strict = DecodeOptions {
+        strict: true,
+        ..Default::default()
+    };
+    standard
+        .qoi_decode_with_options(None, &mut decoded, strict)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+
+    let mut corrupt = standard.clone();
+    *corrupt.last_mut().unwrap() = 0;
+    assert_eq!(
+        corrupt
+            .qoi_decode_with_options(None, &mut decoded, options)
+            .unwrap_err(),
+        QoiError::InvalidPadding
+    );
+
+    let mut decoder = QoiStreamDecoder::with_options(&corrupt[..], None, options).unwrap();
+    let error = loop {
+        if let Err(error) = decoder.read_row(&mut row) {
+            break error;
+        }
+    };
+    assert_eq!(error, QoiError::InvalidPadding);
+}
+
+#[test]
+fn standard_opcodes() {
+    #[rustfmt::skip]
+    let raw = [
+        0, 0, 0, 255,
+        10, 20, 30, 255,
+        11, 21, 30, 255,
+        10, 20, 30, 255,
+        20, 25, 33, 128,
+        24, 30, 39, 128,
+    ];
+
+    let mut expected = header_bytes(6, 1, 4);
+    expected.extend_from_slice(&[
+        0xc0, // RUN of 1
+        0xfe, 10, 20, 30,   // RGB
+        0x7e, // DIFF of +1, +1, 0
+        0x09, // INDEX
+        0xff, 20, 25, 33, 128, // RGBA
+        0xa5, 0x79, // LUMA of +4, +5, +6
+        0, 0, 0, 0, 0, 0, 0, 1,
+    ]);
+
+    let mut encoded = vec![0; expected.len()];
+    let len = raw
+        .qoi_encode_standard(6, 1, Channels::Four, ColourSpace::Srgb, &mut encoded)
+        .unwrap();
+    assert_eq!(len, expected.len());
+    compare_bytes(&encoded, &expected);
+
+    let mut decoded = [0; 24];
+    expected.qoi_decode_standard(None, &mut decoded).unwrap();
+    assert_eq!(decoded, raw);
+
+    let legacy = raw
+        .qoi_encode_to_vec(6, 1, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(
+        legacy.qoi_decode_standard(None, &mut decoded).unwrap_err(),
+        QoiError::InvalidPadding
+    );
+}
+
+#[test]
+fn standard_round_trip() {
+    let mut images = vec![(rgba_test_image(), 8, 8), ([7; 4 * 200].to_vec(), 200, 1)];
+    for_all_qoi_files(|case| {
+        if case.header.channels() == Channels::Four {
+            images.push((case.raw.clone(), case.header.width(), case.header.height()));
+        }
+    });
+
+    for (raw, width, height) in images {
+        for channels in [Channels::Three, Channels::Four] {
+            let raw = match channels {
+                Channels::Three => raw
+                    .chunks_exact(4)
+                    .flat_map(|p| [p[0], p[1], p[2]])
+                    .collect(),
+                Channels::Four => raw.clone(),
+            };
+
+            let header = QoiHeader::new(width, height, channels, ColourSpace::Srgb);
+            let mut encoded = vec![0; header.max_encoded_size() + 4];
+            let len = raw
+                .qoi_encode_standard(width, height, channels, ColourSpace::Srgb, &mut encoded)
+                .unwrap();
+
+            let mut decoded = vec![0; raw.len()];
+            (&encoded[..len])
+                .qoi_decode_standard(None, &mut decoded)
+                .unwrap();
+            compare_bytes(&decoded, &raw);
+        }
+    }
+}
+
+#[test]
+fn standard_reference() {
+    // Encoded by qoi.h from the reference implementation, covering runs
+    // longer than 62 pixels, wrapping diffs, index hits and alpha changes.
+    let cases: [(&[u8], &[u8], Channels); 2] = [
+        (
+            include_bytes!("standard/rgb.raw"),
+            include_bytes!("standard/rgb.qoi"),
+            Channels::Three,
+        ),
+        (
+            include_bytes!("standard/rgba.raw"),
+            include_bytes!("standard/rgba.qoi"),
+            Channels::Four,
+        ),
+    ];
+
+    for (raw, reference, channels) in cases {
+        let mut decoded = vec![0; raw.len()];
+        reference.qoi_decode_standard(None, &mut decoded).unwrap();
+        compare_bytes(&decoded, raw);
+
+        let mut encoded = vec![0; reference.len()];
+        let len = raw
+            .qoi_encode_standard(16, 16, channels, ColourSpace::Srgb, &mut encoded)
+            .unwrap();
+        compare_bytes(&encoded[..len], reference);
+    }
+}
+
+#[test]
+fn standard_colour_space() {
+    let raw = [1, 2, 3, 255];
+    let cases = [
+        (ColourSpace::Srgb, 0, ColourSpace::Srgb),
+        (ColourSpace::SrgbLinearAlpha, 0, ColourSpace::Srgb),
+        (ColourSpace::Linear, 1, ColourSpace::Linear),
+    ];
+
+    for (colour_space, byte, read) in cases {
+        let mut encoded = [0; 32];
+        let len = raw
+            .qoi_encode_standard(1, 1, Channels::Four, colour_space, &mut encoded)
+            .unwrap();
+        let encoded = &encoded[..len];
+        assert_eq!(encoded[13], byte);
+        assert_eq!(
+            encoded.load_qoi_standard_header().unwrap().colour_space(),
+            read
+        );
+
+        let mut decoded = [0; 4];
+        encoded.qoi_decode_standard(None, &mut decoded).unwrap();
+        assert_eq!(decoded, raw);
+    }
+
+    // The legacy value for linear isn't defined by the specification.
+    let mut encoded = [0; 32];
+    let len = raw
+        .qoi_encode_standard(1, 1, Channels::Four, ColourSpace::Srgb, &mut encoded)
+        .unwrap();
+    encoded[13] = 0x0f;
+    let mut decoded = [0; 4];
+    assert_eq!(
+        (&encoded[..len]).qoi_decode_standard(None, &mut decoded),
+        Err(QoiError::InvalidHeader)
+    );
+}
+
+#[test]
+fn crc_trailer() {
+    let raw = rgba_test_image();
+    let plain = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let encode_options = EncodeOptions {
+        with_crc: true,
+        ..Default::default()
+    };
+    let mut encoded = raw
+        .qoi_encode_to_vec_with_options(8, 8, Channels::Four, ColourSpace::Srgb, encode_options)
+        .unwrap();
+    assert_eq!(encoded[..plain.len()], plain);
+    assert_eq!(encoded.len(), plain.len() + 4);
+
+    let mut dest = vec![0; encoded.len()];
+    let len = raw
+        .qoi_encode_with_options(
+            8,
+            8,
+            Channels::Four,
+            ColourSpace::Srgb,
+            &mut dest,
+            encode_options,
+        )
+        .unwrap();
+    assert_eq!(dest[..len], encoded);
+
+    let error = raw
+        .qoi_encode_with_options(
+            8,
+            8,
+            Channels::Four,
+            ColourSpace::Srgb,
+            &mut dest[..len - 1],
+            encode_options,
+        )
+        .unwrap_err();
+    assert_eq!(
+        error,
+        QoiError::OutputTooSmall {
+            needed: len,
+            got: len - 1
+        }
+    );
+
+    let options = DecodeOptions {
+        verify_crc: true,
+        ..Default::default()
+    };
+    let mut decoded = vec![0; raw.len()];
+    encoded
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+
+    // Plain streams are still accepted by default.
+    plain.qoi_decode(None, &mut decoded).unwrap();
+    compare_bytes(&decoded, &raw);
+
+    let last = encoded.len() - 1;
+    encoded[last] ^= 1;
+    let error = encoded
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap_err();
+    assert_eq!(error, QoiError::ChecksumMismatch);
+
+    let error = plain
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap_err();
+    assert_eq!(error, QoiError::ChecksumMismatch);
+}
+
+#[test]
+fn header_rewrite_in() {
+    let raw = rgba_test_image();
+    let mut encoded = raw
+        .qoi_encode_to_vec(16, 4, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut header = encoded.load_qoi_header().unwrap();
+    header.set_width(4);
+    header.set_height(16);
+    header.rewrite_in(&mut encoded).unwrap();
+
+    let header = encoded.load_qoi_header().unwrap();
+    assert_eq!((header.width(), header.height()), (4, 16));
+    compare_bytes(&encoded.qoi_decode_to_vec(None).unwrap(), &raw);
+
+    assert_eq!(
+        header.rewrite_in(&mut encoded[..13]),
+        Err(QoiError::InputSmallerThanHeader)
+    );
+
+    let mut raw = raw;
+    assert_eq!(
+        header.rewrite_in(&mut raw),
+        Err(QoiError::IncorrectHeaderMagic)
+    );
+}
+
+#[test]
+fn atlas() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let small = [1, 2, 3, 4, 5, 6];
+
+    let mut writer = AtlasWriter::new();
+    writer.add_encoded(&encoded).unwrap();
+    writer
+        .add_raw(&small, 2, 1, Channels::Three, ColourSpace::Linear)
+        .unwrap();
+    assert_eq!(
+        writer.add_encoded(b"not an image"),
+        Err(QoiError::InputSmallerThanHeader)
+    );
+    let atlas = writer.finish().unwrap();
+
+    let reader = AtlasReader::new(&atlas).unwrap();
+    assert_eq!(reader.len(), 2);
+    assert_eq!(reader.get(0), Some(&encoded[..]));
+    assert_eq!(reader.dimensions(0), Some((8, 8)));
+    assert_eq!(reader.dimensions(1), Some((2, 1)));
+    assert_eq!(reader.get(2), None);
+    assert_eq!(
+        reader.get(1).unwrap().qoi_decode_to_vec(None).unwrap(),
+        small
+    );
+
+    assert_eq!(
+        AtlasReader::new(&atlas[..atlas.len() - 1]).unwrap_err(),
+        QoiError::InputSize
+    );
+    assert_eq!(
+        AtlasReader::new(&encoded).unwrap_err(),
+        QoiError::IncorrectHeaderMagic
+    );
+    assert!(AtlasReader::new(&AtlasWriter::new().finish().unwrap())
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn decode_premultiplied() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let options = DecodeOptions {
+        premultiply_alpha: true,
+        ..Default::default()
+    };
+
+    let expected: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| {
+            let multiply = |c: u8| (c as u16 * p[3] as u16 / 255) as u8;
+            [multiply(p[0]), multiply(p[1]), multiply(p[2]), p[3]]
+        })
+        .collect();
+
+    let mut decoded = vec![0; raw.len()];
+    encoded
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap();
+    compare_bytes(&decoded, &expected);
+
+    let mut decoder = QoiStreamDecoder::with_options(&encoded[..], None, options).unwrap();
+    for row in decoded.chunks_exact_mut(32) {
+        decoder.read_row(row).unwrap();
+    }
+    compare_bytes(&decoded, &expected);
+
+    assert_eq!(
+        Pixel::new(255, 128, 0, 128).premultiplied(),
+        Pixel::new(128, 64, 0, 128)
+    );
+
+    // Decoding to 3 channels is unaffected.
+    let mut rgb = vec![0; 8 * 8 * 3];
+    encoded
+        .qoi_decode_with_options(Some(Channels::Three), &mut rgb, options)
+        .unwrap();
+    assert_eq!(
+        rgb,
+        encoded.qoi_decode_to_vec(Some(Channels::Three)).unwrap()
+    );
+}
+
+#[test]
+fn decode_transformed() {
+    for channels in [Channels::Three, Channels::Four] {
+        let pixel_size = channels.count() as usize;
+        let raw: Vec<u8> = rgba_test_image()
+            .chunks_exact(4)
+            .flat_map(|p| p[..pixel_size].to_vec())
+            .collect();
+        let encoded = raw
+            .qoi_encode_to_vec(16, 4, channels, ColourSpace::Srgb)
+            .unwrap();
+
+        for (flip_v, flip_h) in [(false, false), (true, false), (false, true), (true, true)] {
+            let mut rows: Vec<Vec<u8>> = raw
+                .chunks_exact(16 * pixel_size)
+                .map(<[u8]>::to_vec)
+                .collect();
+            if flip_v {
+                rows.reverse();
+            }
+            if flip_h {
+                for row in &mut rows {
+                    let mut pixels: Vec<&[u8]> = row.chunks_exact(pixel_size).collect();
+                    pixels.reverse();
+                    *row = pixels.concat();
+                }
+            }
+            let expected = rows.concat();
+
+            let options = DecodeOptions {
+                transform: Transform { flip_v, flip_h },
+                ..Default::default()
+            };
+            let mut decoded = vec![0; raw.len()];
+            encoded
+                .qoi_decode_with_options(None, &mut decoded, options)
+                .unwrap();
+            compare_bytes(&decoded, &expected);
+
+            if flip_v || flip_h {
+                let error = encoded
+                    .qoi_decode_with_options(None, &mut decoded[1..], options)
+                    .unwrap_err();
+                assert_eq!(
+                    error,
+                    QoiError::OutputTooSmall {
+                        needed: raw.len(),
+                        got: raw.len() - 1
+                    }
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn decode_into_vec() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut decoded = Vec::with_capacity(1024);
+    decoded.extend_from_slice(&[1; 300]);
+    let capacity = decoded.capacity();
+
+    encoded.qoi_decode_into_vec(None, &mut decoded).unwrap();
+    compare_bytes(&decoded, &raw);
+    assert_eq!(decoded.capacity(), capacity);
+
+    encoded
+        .qoi_decode_into_vec(Some(Channels::Three), &mut decoded)
+        .unwrap();
+    assert_eq!(
+        decoded,
+        encoded.qoi_decode_to_vec(Some(Channels::Three)).unwrap()
+    );
+    assert_eq!(decoded.capacity(), capacity);
+}
+
+#[test]
+fn decode_with_progress() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(16, 4, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut calls = Vec::new();
+    let mut decoded = vec![0; raw.len()];
+    encoded
+        .qoi_decode_with_progress(None, &mut decoded, |done, total| calls.push((done, total)))
+        .unwrap();
+
+    compare_bytes(&decoded, &raw);
+    assert_eq!(calls, [(16, 64), (32, 64), (48, 64), (64, 64)]);
+}
+
+#[test]
+fn require_header_channels() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let options = DecodeOptions {
+        require_header_channels: true,
+        ..Default::default()
+    };
+
+    let mut decoded = vec![0; raw.len()];
+    encoded
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap();
+    encoded
+        .qoi_decode_with_options(Some(Channels::Four), &mut decoded, options)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+
+    let error = encoded
+        .qoi_decode_with_options(Some(Channels::Three), &mut decoded, options)
+        .unwrap_err();
+    assert_eq!(error, QoiError::ChannelMismatch);
+
+    let error = QoiStreamDecoder::with_options(&encoded[..], Some(Channels::Three), options)
+        .err()
+        .unwrap();
+    assert_eq!(error, QoiError::ChannelMismatch);
+
+    encoded
+        .qoi_decode(Some(Channels::Three), &mut decoded)
+        .unwrap();
+}
+
+#[test]
+fn decode_with_channel_order() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    // The source channel written to each output position.
+    let cases: [(Channels, ChannelOrder, &[usize]); 6] = [
+        (Channels::Four, ChannelOrder::Rgba, &[0, 1, 2, 3]),
+        (Channels::Four, ChannelOrder::Bgra, &[2, 1, 0, 3]),
+        (Channels::Four, ChannelOrder::Argb, &[3, 0, 1, 2]),
+        (Channels::Three, ChannelOrder::Rgba, &[0, 1, 2]),
+        (Channels::Three, ChannelOrder::Bgra, &[2, 1, 0]),
+        (Channels::Three, ChannelOrder::Argb, &[0, 1, 2]),
+    ];
+
+    for (channels, order, swizzle) in cases {
+        let expected: Vec<u8> = raw
+            .chunks_exact(4)
+            .flat_map(|p| swizzle.iter().map(|&i| p[i]))
+            .collect();
+        let mut decoded = vec![0; expected.len()];
+        encoded
+            .qoi_decode_with_order(Some(channels), order, &mut decoded)
+            .unwrap();
+        compare_bytes(&decoded, &expected);
+    }
+
+    // Anything past the image is left untouched.
+    let mut decoded = vec![7; 8 * 8 * 4 + 4];
+    encoded
+        .qoi_decode_with_order(None, ChannelOrder::Bgra, &mut decoded)
+        .unwrap();
+    assert_eq!(decoded[8 * 8 * 4..], [7; 4]);
+
+    let mut decoded = vec![0; 8 * 8 * 4 - 1];
+    assert_eq!(
+        encoded.qoi_decode_with_order(None, ChannelOrder::Bgra, &mut decoded),
+        Err(QoiError::OutputTooSmall {
+            needed: 256,
+            got: 255
+        })
+    );
+}
+
+#[test]
+fn encode_with_channel_order() {
+    let raw = rgba_test_image();
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let bgra: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[2], p[1], p[0], p[3]])
+        .collect();
+    let options = EncodeOptions {
+        channel_order: ChannelOrder::Bgra,
+        ..Default::default()
+    };
+    let encoded = bgra
+        .qoi_encode_to_vec_with_options(8, 8, Channels::Four, ColourSpace::Srgb, options)
+        .unwrap();
+    compare_bytes(&encoded, &expected);
+
+    let rgb: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    let bgr: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[2], p[1], p[0]])
+        .collect();
+    let expected = rgb
+        .qoi_encode_to_vec(8, 8, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    let mut encoded = vec![0; expected.len()];
+    let len = bgr
+        .qoi_encode_with_options(
+            8,
+            8,
+            Channels::Three,
+            ColourSpace::Srgb,
+            &mut encoded,
+            options,
+        )
+        .unwrap();
+    compare_bytes(&encoded[..len], &expected);
+}
+
+#[test]
+fn encode_from_iter() {
+    let raw = rgba_test_image();
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let pixels = raw.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]);
+    let mut encoded = vec![0; expected.len()];
+    let len = qoi::qoi_encode_from_iter(pixels, 8, 8, ColourSpace::Srgb, &mut encoded).unwrap();
+    compare_bytes(&encoded[..len], &expected);
+
+    let pixels = raw.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]);
+    let error = qoi::qoi_encode_from_iter(
+        pixels.clone().take(63),
+        8,
+        8,
+        ColourSpace::Srgb,
+        &mut encoded,
+    )
+    .unwrap_err();
+    assert_eq!(error, QoiError::InputSize);
+
+    let error = qoi::qoi_encode_from_iter(
+        pixels.cycle().take(65),
+        8,
+        8,
+        ColourSpace::Srgb,
+        &mut encoded,
+    )
+    .unwrap_err();
+    assert_eq!(error, QoiError::InputSize);
+}
+
+#[test]
+fn encode_to_writer() {
+    let raw = rgba_test_image();
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut encoded = Vec::new();
+    let len = raw
+        .qoi_encode_to_writer(8, 8, Channels::Four, ColourSpace::Srgb, &mut encoded)
+        .unwrap();
+    assert_eq!(len, expected.len());
+    compare_bytes(&encoded, &expected);
+
+    let mut full = [0u8; 16];
+    let error = raw
+        .qoi_encode_to_writer(8, 8, Channels::Four, ColourSpace::Srgb, &mut full[..])
+        .unwrap_err();
+    assert!(matches!(error, QoiError::Io(e) if e.kind() == std::io::ErrorKind::WriteZero));
+}
+
+#[test]
+fn encode_size_limit_uses_requested_channels() {
+    // The worst case encoding of 16384 * 14000 pixels fits within the size
+    // limit using 3 channels but not 4.
+    let error = [0u8; 4]
+        .qoi_encode_to_vec(16384, 14000, Channels::Three, ColourSpace::Srgb)
+        .unwrap_err();
+    assert!(matches!(error, QoiError::InputSize));
+
+    let error = [0u8; 4]
+        .qoi_encode_to_vec(16384, 14000, Channels::Four, ColourSpace::Srgb)
+        .unwrap_err();
+    assert_eq!(
+        error,
+        QoiError::TooBig {
+            requested: 16384 * 14000 * 5 + 14 + 4,
+            max: Qoi::MAX_SIZE
+        }
+    );
+}
+
+#[test]
+fn encode_to_vec_too_big_and_short_input() {
+    // Oversized dimensions are reported before the input length is checked.
+    let error = [0u8; 4]
+        .qoi_encode_to_vec(u32::MAX, u32::MAX, Channels::Three, ColourSpace::Srgb)
+        .unwrap_err();
+    assert_eq!(
+        error,
+        QoiError::TooBig {
+            requested: usize::MAX,
+            max: Qoi::MAX_SIZE
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "The image size is too big, {} is requested but the limit is {}",
+            usize::MAX,
+            Qoi::MAX_SIZE
+        )
+    );
+
+    // Dimensions which fit with an input which is too short.
+    let error = [0u8; 11]
+        .qoi_encode_to_vec(2, 2, Channels::Three, ColourSpace::Srgb)
+        .unwrap_err();
+    assert_eq!(error, QoiError::InputSize);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn encode_timed() {
+    let raw = rgba_test_image();
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut encoded = vec![0; 1024];
+    let (len, timings) = raw
+        .qoi_encode_timed(8, 8, Channels::Four, ColourSpace::Srgb, &mut encoded)
+        .unwrap();
+    compare_bytes(&encoded[..len], &expected);
+    assert_eq!(
+        timings.total(),
+        timings.header + timings.body + timings.padding
+    );
+
+    assert_eq!(
+        raw.qoi_encode_timed(8, 9, Channels::Four, ColourSpace::Srgb, &mut encoded),
+        Err(QoiError::InputSize)
+    );
+}
+
+#[cfg(feature = "instrument")]
+#[test]
+fn instrumented_calls_accumulate() {
+    let raw = rgba_test_image();
+    let mut encoded = vec![0; 1024];
+    let mut decoded = vec![0; raw.len()];
+    let mut metrics = qoi::Metrics::new();
+
+    let len = raw
+        .qoi_encode_instrumented(
+            8,
+            8,
+            Channels::Four,
+            ColourSpace::Srgb,
+            &mut encoded,
+            &mut metrics,
+        )
+        .unwrap();
+    (&encoded[..len])
+        .qoi_decode_instrumented(None, &mut decoded, &mut metrics)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+
+    // Failed calls aren't recorded.
+    assert!(raw
+        .qoi_encode_instrumented(
+            8,
+            8,
+            Channels::Four,
+            ColourSpace::Srgb,
+            [0; 4],
+            &mut metrics
+        )
+        .is_err());
+
+    assert_eq!(metrics.calls, 2);
+    assert_eq!(metrics.bytes, 2 * raw.len() as u64);
+}
+
+#[test]
+fn ring_decode_keeps_last_rows() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let mut decoder = encoded.qoi_decode_ring(None, 3).unwrap();
+    let row_size = 8 * 4;
+
+    let mut y = 0;
+    while let Some(row) = decoder.decode_row().unwrap() {
+        compare_bytes(row, &raw[y * row_size..(y + 1) * row_size]);
+        y += 1;
+    }
+    assert_eq!(y, 8);
+
+    assert_eq!(decoder.valid_rows(), 5..8);
+    assert!(decoder.row(4).is_none());
+    let window: Vec<u8> = decoder.window().flatten().copied().collect();
+    compare_bytes(&window, &raw[5 * row_size..]);
+
+    let error = encoded.qoi_decode_ring(None, 0).err().unwrap();
+    assert_eq!(error, QoiError::EmptyImage);
+}
+
+#[test]
+fn rows_iterator() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let rows: Vec<Vec<u8>> = encoded
+        .qoi_rows(Some(Channels::Three))
+        .unwrap()
+        .skip(2)
+        .take(3)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let expected: Vec<u8> = raw[2 * 32..5 * 32]
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    compare_bytes(&rows.concat(), &expected);
+
+    let mut rows = encoded.qoi_rows(None).unwrap();
+    let mut decoded = Vec::new();
+    while let Some(row) = rows.next_row() {
+        decoded.extend_from_slice(row.unwrap());
+    }
+    compare_bytes(&decoded, &raw);
+
+    // A COLOR running into the padding, which ends the rows.
+    let mut truncated = header_bytes(2, 2, 4);
+    truncated.extend_from_slice(&[0xff, 1, 2, 3, 4, 0, 0]);
+    let mut rows = truncated.qoi_rows(None).unwrap();
+    assert!(rows.next_row().unwrap().is_err());
+    assert!(rows.next_row().is_none());
+
+    let mut wide = header_bytes(0x4000_0000, 1, 4);
+    wide.extend_from_slice(&[0, 0, 0, 0]);
+    assert!(matches!(wide.qoi_rows(None), Err(QoiError::TooBig { .. })));
+}
+
+#[test]
+fn decode_rows_callback() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut decoded = Vec::new();
+    let mut next_row = 0;
+    encoded
+        .qoi_decode_rows(None, |y, row| {
+            assert_eq!(y, next_row);
+            assert_eq!(row.len(), 32);
+            next_row += 1;
+            decoded.extend_from_slice(row);
+        })
+        .unwrap();
+
+    assert_eq!(next_row, 8);
+    compare_bytes(&decoded, &raw);
+
+    let mut wide = header_bytes(0x4000_0000, 1, 4);
+    wide.extend_from_slice(&[0, 0, 0, 0]);
+    assert!(matches!(
+        wide.qoi_decode_rows(None, |_, _| {}),
+        Err(QoiError::TooBig { .. })
+    ));
+}
+
+#[test]
+fn analyze_batch() {
+    let opaque = [1, 2, 3, 255].repeat(4);
+    let translucent = [1, 2, 3, 255, 1, 2, 3, 128].repeat(2);
+
+    let recommendation = qoi::qoi_analyze_batch(&[&opaque, &opaque], 2, 2).unwrap();
+    assert_eq!(recommendation.channels, Channels::Three);
+    assert_eq!(recommendation.colour_space, ColourSpace::Srgb);
+
+    let recommendation = qoi::qoi_analyze_batch(&[&opaque, &translucent], 2, 2).unwrap();
+    assert_eq!(recommendation.channels, Channels::Four);
+    assert_eq!(recommendation.colour_space, ColourSpace::SrgbLinearAlpha);
+
+    let error = qoi::qoi_analyze_batch(&[&opaque, &opaque[..15]], 2, 2).unwrap_err();
+    assert!(matches!(error, QoiError::InputSize));
+}
+
+#[test]
+fn decode_to_ppm() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let mut pam = Vec::new();
+    encoded.qoi_decode_to_ppm(&mut pam).unwrap();
+
+    let header = b"P7\nWIDTH 8\nHEIGHT 8\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+    assert_eq!(&pam[..header.len()], header);
+    compare_bytes(&pam[header.len()..], &raw);
+
+    let rgb: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    let encoded = rgb
+        .qoi_encode_to_vec(8, 8, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    let mut ppm = Vec::new();
+    encoded.qoi_decode_to_ppm(&mut ppm).unwrap();
+
+    let header = b"P6\n8 8\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    compare_bytes(&ppm[header.len()..], &rgb);
+    assert_eq!(encoded.qoi_decode_to_ppm_vec().unwrap(), ppm);
+
+    let rgba = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(rgba.qoi_decode_to_ppm_vec().unwrap(), ppm);
+
+    // Nothing is written for an image too wide to decode.
+    let mut wide = header_bytes(0x4000_0000, 1, 4);
+    wide.extend_from_slice(&[0, 0, 0, 0]);
+    let mut out = Vec::new();
+    assert!(matches!(
+        wide.qoi_decode_to_ppm(&mut out),
+        Err(QoiError::TooBig { .. })
+    ));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn stream_encode() {
+    let raw = rgba_test_image();
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut encoder = QoiStreamEncoder::new(Vec::new());
+    encoder
+        .write_header(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    // Split pixels across calls.
+    for chunk in raw.chunks(7) {
+        encoder.write_pixels(chunk).unwrap();
+    }
+    let encoded = encoder.finish().unwrap();
+    compare_bytes(&encoded, &expected);
+}
+
+#[test]
+fn stream_encode_pixel_count() {
+    let mut encoder = QoiStreamEncoder::new(Vec::new());
+    assert!(matches!(
+        encoder.write_pixels(&[0; 3]).unwrap_err(),
+        QoiError::InvalidHeader
+    ));
+
+    encoder
+        .write_header(1, 1, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    assert!(matches!(
+        encoder.write_pixels(&[0; 6]).unwrap_err(),
+        QoiError::InputSize
+    ));
+
+    let mut encoder = QoiStreamEncoder::new(Vec::new());
+    encoder
+        .write_header(2, 1, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    encoder.write_pixels(&[0; 3]).unwrap();
+    assert!(matches!(encoder.finish().unwrap_err(), QoiError::InputSize));
+}
+
+#[test]
+fn rewrite_header() {
+    let raw = [1, 2, 3, 255].repeat(4);
+    let encoded = raw
+        .qoi_encode_to_vec(2, 2, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let rewritten = encoded
+        .qoi_rewrite_header(&QoiHeader::new(
+            2,
+            2,
+            Channels::Three,
+            ColourSpace::SrgbLinearAlpha,
+        ))
+        .unwrap();
+    let header = rewritten.load_qoi_header().unwrap();
+    assert_eq!(header.channels(), Channels::Three);
+    assert_eq!(header.colour_space(), ColourSpace::SrgbLinearAlpha);
+    compare_bytes(&rewritten[14..], &encoded[14..]);
+
+    let translucent = rgba_test_image()
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let error = translucent
+        .qoi_rewrite_header(&QoiHeader::new(8, 8, Channels::Three, ColourSpace::Srgb))
+        .unwrap_err();
+    assert!(matches!(error, QoiError::Channels));
+
+    // Reshaping keeps the pixel count, so 4x16 is allowed but 8x9 and 0x8 aren't.
+    let reshaped = translucent
+        .qoi_rewrite_header(&QoiHeader::new(4, 16, Channels::Four, ColourSpace::Srgb))
+        .unwrap();
+    assert_eq!(reshaped.qoi_dimensions().unwrap(), (4, 16));
+    assert_eq!(
+        translucent
+            .qoi_rewrite_header(&QoiHeader::new(8, 9, Channels::Four, ColourSpace::Srgb))
+            .unwrap_err(),
+        QoiError::InputSize
+    );
+    assert_eq!(
+        translucent
+            .qoi_rewrite_header(&QoiHeader::new(0, 8, Channels::Four, ColourSpace::Srgb))
+            .unwrap_err(),
+        QoiError::EmptyImage
+    );
+}
+
+/// Returns at most `max` bytes per read to split opcodes across reads.
+struct ShortReads<'a> {
+    data: &'a [u8],
+    max: usize,
+}
+
+impl<'a> Read for ShortReads<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.max.min(buf.len()).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+#[test]
+fn stream_decode() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    for max in [1, 2, 5, 4096] {
+        let reader = ShortReads {
+            data: &encoded,
+            max,
+        };
+        let mut decoder = QoiStreamDecoder::new(reader, None).unwrap();
+        assert_eq!(decoder.header().width(), 8);
+
+        let mut decoded = Vec::new();
+        let mut row = [0; 32];
+        while decoder.read_row(&mut row).unwrap() {
+            decoded.extend_from_slice(&row);
+        }
+        compare_bytes(&decoded, &raw);
+    }
+}
+
+#[test]
+fn stream_decode_truncated_padding() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let truncated = &encoded[..encoded.len() - 1];
+
+    let mut decoder = QoiStreamDecoder::new(truncated, None).unwrap();
+    let mut row = [0; 32];
+    let error = loop {
+        if let Err(error) = decoder.read_row(&mut row) {
+            break error;
+        }
+    };
+    assert!(matches!(error, QoiError::InputSize));
+
+    let error = QoiStreamDecoder::new(&encoded[..13], None).err().unwrap();
+    assert!(matches!(error, QoiError::InputSmallerThanHeader));
+
+    // Options which need the whole image are rejected rather than ignored.
+    let unsupported = [
+        DecodeOptions {
+            transform: Transform {
+                flip_v: true,
+                flip_h: false,
+            },
+            ..Default::default()
+        },
+        DecodeOptions {
+            verify_crc: true,
+            ..Default::default()
+        },
+    ];
+    for options in unsupported {
+        let error = QoiStreamDecoder::with_options(&encoded[..], None, options)
+            .err()
+            .unwrap();
+        assert_eq!(error, QoiError::UnsupportedFormat);
+    }
+}
+
+#[test]
+fn stream_decode_max_total_pixels() {
+    // A 1000x1000 image consisting of maximum length runs.
+    let mut encoded = header_bytes(1000, 1000, 3);
+    encoded.extend([0x7f, 0xff].repeat(122));
+    encoded.extend_from_slice(&[0; 4]);
+
+    let options = DecodeOptions {
+        max_total_pixels: Some(10_000),
+        ..Default::default()
+    };
+    let mut decoder = QoiStreamDecoder::with_options(&encoded[..], None, options).unwrap();
+    let mut row = [0; 3000];
+    for _ in 0..10 {
+        assert!(decoder.read_row(&mut row).unwrap());
+    }
+    assert_eq!(
+        decoder.read_row(&mut row).unwrap_err(),
+        QoiError::TooBig {
+            requested: 11_000,
+            max: 10_000
+        }
+    );
+}
+
+#[test]
+fn reusable_encoder() {
+    let raw = rgba_test_image();
+    let expected = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let mut encoder = QoiEncoder::new();
+    let mut output = Vec::with_capacity(1024);
+
+    for _ in 0..3 {
+        encoder
+            .encode_into(&raw, 8, 8, Channels::Four, ColourSpace::Srgb, &mut output)
+            .unwrap();
+        compare_bytes(&output, &expected);
+        assert_eq!(output.capacity(), 1024);
+
+        let encoded = encoder
+            .encode(&raw, 8, 8, Channels::Four, ColourSpace::Srgb)
+            .unwrap();
+        compare_bytes(encoded, &expected);
+    }
+}
+
+#[test]
+fn decode_grayscale() {
+    let raw = [
+        255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, 10, 10, 10, 255,
+    ];
+    let encoded = raw
+        .qoi_encode_to_vec(2, 2, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut gray = [0; 4];
+    encoded.qoi_decode_grayscale(&mut gray).unwrap();
+    assert_eq!(gray, [76, 150, 29, 10]);
+
+    let error = encoded.qoi_decode_grayscale([0; 3]).unwrap_err();
+    assert_eq!(error, QoiError::OutputTooSmall { needed: 4, got: 3 });
+}
+
+#[test]
+fn decode_region() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut region = [0; 3 * 2 * 3];
+    encoded
+        .qoi_decode_region(2, 5, 3, 2, Some(Channels::Three), &mut region)
+        .unwrap();
+
+    let expected: Vec<u8> = (5..7)
+        .flat_map(|y| (2..5).map(move |x| (y * 8 + x) * 4))
+        .flat_map(|i| [raw[i], raw[i + 1], raw[i + 2]])
+        .collect();
+    compare_bytes(&region, &expected);
+
+    let error = encoded
+        .qoi_decode_region(6, 0, 3, 1, None, [0; 12])
+        .unwrap_err();
+    assert!(matches!(error, QoiError::Region));
+}
+
+#[test]
+fn decode_checked() {
+    let raw = rgba_test_image();
+    let mut encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let hash = encoded.qoi_content_hash(None).unwrap();
+    assert_ne!(
+        hash,
+        encoded.qoi_content_hash(Some(Channels::Three)).unwrap()
+    );
+
+    let mut decoded = vec![0; raw.len()];
+    encoded
+        .qoi_decode_checked(hash, None, &mut decoded)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+
+    // Corrupt the last COLOR command's alpha.
+    let len = encoded.len();
+    encoded[len - 5] ^= 1;
+    let error = encoded
+        .qoi_decode_checked(hash, None, &mut decoded)
+        .unwrap_err();
+    assert!(matches!(error, QoiError::ChecksumMismatch));
+}
+
+#[test]
+fn index_uses_pixel_cache_index() {
+    // The second pixel is encoded as an INDEX command referring to the first.
+    let first = Pixel::new(10, 200, 30, 255);
+    let raw = [10, 200, 30, 255, 0, 0, 0, 255, 10, 200, 30, 255];
+    let encoded = raw
+        .qoi_encode_to_vec(3, 1, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(encoded[encoded.len() - 5], first.cache_index_xor() as u8);
+
+    let options = EncodeOptions {
+        hash_mode: HashMode::Weighted,
+        ..Default::default()
+    };
+    let encoded = raw
+        .qoi_encode_to_vec_with_options(3, 1, Channels::Four, ColourSpace::Srgb, options)
+        .unwrap();
+    assert_eq!(encoded[encoded.len() - 5], first.cache_index() as u8);
+    assert_eq!(Pixel::default(), Pixel::new(0, 0, 0, 0));
+}
+
+#[test]
+fn strict_decode_checks_padding() {
+    let raw = rgba_test_image();
+    let mut encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let len = encoded.len();
+    encoded[len - 1] = 1;
+
+    let mut decoded = vec![0; raw.len()];
+    encoded.qoi_decode(None, &mut decoded).unwrap();
+
+    let options = DecodeOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let error = encoded
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap_err();
+    assert!(matches!(error, QoiError::InvalidPadding));
+
+    let mut decoder = QoiStreamDecoder::with_options(&encoded[..], None, options).unwrap();
+    let mut row = [0; 32];
+    let error = loop {
+        if let Err(error) = decoder.read_row(&mut row) {
+            break error;
+        }
+    };
+    assert!(matches!(error, QoiError::InvalidPadding));
+}
+
+#[test]
+fn strict_decode_checks_pixel_count() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    // Only the first pixel is encoded, as a DIFF_8 with no changes.
+    let mut truncated = header_bytes(8, 8, 4);
+    truncated.extend_from_slice(&[0xaa, 0, 0, 0, 0]);
+
+    let mut decoded = vec![0; raw.len()];
+    truncated.qoi_decode(None, &mut decoded).unwrap();
+    compare_bytes(&decoded, &[0, 0, 0, 255].repeat(64));
+
+    let options = DecodeOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let error = truncated
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap_err();
+    assert_eq!(error, QoiError::UnexpectedEnd { pos: 15 });
+
+    encoded
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+}
+
+#[test]
+fn validate() {
+    for_all_qoi_files(|case| {
+        println!("Testing {}", case.path.display());
+        let header = case.encoded.qoi_validate().unwrap();
+        assert_eq!(header.width(), case.header.width());
+        assert_eq!(header.height(), case.header.height());
+    });
+
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    encoded.qoi_validate().unwrap();
+
+    let mut truncated = encoded[..encoded.len() - 12].to_vec();
+    truncated.extend_from_slice(&[0; 4]);
+    assert!(matches!(
+        truncated.qoi_validate().unwrap_err(),
+        QoiError::UnexpectedEnd { .. }
+    ));
+
+    let mut extended = encoded[..encoded.len() - 4].to_vec();
+    // A RUN_8 opcode after the last pixel.
+    extended.extend_from_slice(&[0x40, 0, 0, 0, 0]);
+    assert_eq!(extended.qoi_validate().unwrap_err(), QoiError::InputSize);
+
+    let mut padding = encoded.clone();
+    *padding.last_mut().unwrap() = 1;
+    assert_eq!(
+        padding.qoi_validate().unwrap_err(),
+        QoiError::InvalidPadding
+    );
+
+    let mut trailing = encoded.clone();
+    trailing.extend_from_slice(b"metadata");
+    assert_eq!(
+        trailing.qoi_validate().unwrap(),
+        encoded.qoi_validate().unwrap()
+    );
+}
+
+#[test]
+fn stats() {
+    let mut encoded = header_bytes(5, 1, 4);
+    // RUN_8 of 3 pixels, DIFF_8, COLOR with blue and alpha, and a trailing
+    // INDEX after the last pixel which isn't counted.
+    encoded.extend_from_slice(&[0x42, 0xaa, 0xf3, 1, 2, 0x00, 0, 0, 0, 0]);
+
+    let stats = encoded.qoi_stats().unwrap();
+    assert_eq!(stats.run_8, OpcodeStats { count: 1, bytes: 1 });
+    assert_eq!(stats.diff_8, OpcodeStats { count: 1, bytes: 1 });
+    assert_eq!(stats.color, OpcodeStats { count: 1, bytes: 3 });
+    assert_eq!(stats.index, OpcodeStats::default());
+    assert_eq!(stats.opcodes(), 3);
+    assert_eq!(stats.bytes(), 5);
+
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let stats = encoded.qoi_stats().unwrap();
+    assert_eq!(stats.bytes(), encoded.len() - 18);
+}
+
+#[test]
+fn channels_strings() {
+    for (s, expected) in [
+        ("3", Channels::Three),
+        ("rgb", Channels::Three),
+        ("RGB", Channels::Three),
+        ("4", Channels::Four),
+        ("rgba", Channels::Four),
+        ("RgbA", Channels::Four),
+    ] {
+        assert_eq!(s.parse::<Channels>(), Ok(expected), "{s}");
+    }
+
+    for s in ["", "5", "rgbx", " rgb", "three"] {
+        assert_eq!(s.parse::<Channels>(), Err(QoiError::Channels), "{s}");
+    }
+
+    assert_eq!(Channels::Three.to_string(), "rgb");
+    assert_eq!(Channels::Four.to_string(), "rgba");
+    assert_eq!("rgba".parse::<Channels>().unwrap().to_string(), "rgba");
+}
+
+#[test]
+fn compare_decoded() {
+    let a = rgba_test_image();
+    assert_eq!(qoi::compare_decoded(&a, &a, 8, Channels::Four), None);
+
+    let mut b = a.clone();
+    b[4 * (2 * 8 + 5) + 3] ^= 1;
+    b[4 * (6 * 8 + 1)] ^= 1;
+    assert_eq!(
+        qoi::compare_decoded(&a, &b, 8, Channels::Four),
+        Some((5, 2))
+    );
+
+    // The first missing pixel of a shorter image, including a partial one.
+    assert_eq!(
+        qoi::compare_decoded(&a, &a[..4 * 20], 8, Channels::Four),
+        Some((4, 2))
+    );
+    assert_eq!(
+        qoi::compare_decoded(&a[..4 * 20 + 2], &a, 8, Channels::Four),
+        Some((4, 2))
+    );
+}
+
+#[test]
+fn decode_alpha_and_planes() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let alpha = encoded.qoi_decode_alpha().unwrap();
+    let expected: Vec<u8> = raw.chunks_exact(4).map(|p| p[3]).collect();
+    assert_eq!(alpha, expected);
+
+    let planes = encoded.qoi_decode_rgb_planar().unwrap();
+    for (channel, plane) in planes.iter().enumerate() {
+        let expected: Vec<u8> = raw.chunks_exact(4).map(|p| p[channel]).collect();
+        assert_eq!(plane, &expected, "channel {channel}");
+    }
+
+    let rgb: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    let encoded = rgb
+        .qoi_encode_to_vec(8, 8, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(encoded.qoi_decode_alpha().unwrap(), vec![255; 64]);
+    assert_eq!(encoded.qoi_decode_rgb_planar().unwrap(), planes);
+}
+
+#[test]
+fn decode_thumbnail() {
+    // 5x3 pixels where each channel is the pixel's index.
+    let raw: Vec<u8> = (0..15u8).flat_map(|i| [i, i * 2, i * 3]).collect();
+    let encoded = raw
+        .qoi_encode_to_vec(5, 3, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+
+    // Blocks of 2x2, with partial blocks on the right and bottom edges.
+    let thumbnail = encoded.qoi_decode_thumbnail(3).unwrap();
+    assert_eq!((thumbnail.width, thumbnail.height), (3, 2));
+    assert_eq!(thumbnail.channels, Channels::Three);
+    let reds: Vec<u8> = thumbnail.data.chunks_exact(3).map(|p| p[0]).collect();
+    // (0 + 1 + 5 + 6) / 4, (2 + 3 + 7 + 8) / 4, (4 + 9) / 2 rounded, and so on.
+    assert_eq!(reds, [3, 5, 7, 11, 13, 14]);
+    assert_eq!(thumbnail.pixel(2, 1).unwrap(), [14, 28, 42]);
+
+    let full = encoded.qoi_decode_thumbnail(5).unwrap();
+    assert_eq!(full, encoded.qoi_decode_to_image(None).unwrap());
+
+    assert_eq!(encoded.qoi_decode_thumbnail(0), Err(QoiError::EmptyImage));
+
+    // Only the thumbnail is allocated, so a small one of a huge image is
+    // fine. A RUN_16 covers the first 8192 pixels, and the rest repeat it.
+    let mut huge = header_bytes(0x1_0000, 0x1_0000, 4);
+    huge.extend_from_slice(&[0x7f, 0xff, 0, 0, 0, 0]);
+    let thumbnail = huge.qoi_decode_thumbnail(4).unwrap();
+    assert_eq!((thumbnail.width, thumbnail.height), (4, 4));
+    assert_eq!(thumbnail.data, [0, 0, 0, 255].repeat(16));
+    assert!(matches!(
+        huge.qoi_decode_thumbnail(u32::MAX),
+        Err(QoiError::TooBig { .. })
+    ));
+}
+
+#[test]
+fn uses_alpha() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert!(encoded.qoi_uses_alpha().unwrap());
+
+    // Opaque pixels don't use alpha even when the header has 4 channels.
+    let opaque: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2], 255])
+        .collect();
+    let encoded = opaque
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert!(!encoded.qoi_uses_alpha().unwrap());
+
+    // An INDEX into an unused cache entry is transparent, even with 3
+    // channels.
+    let mut encoded = header_bytes(2, 1, 3);
+    encoded.extend_from_slice(&[0x80, 0x05, 0, 0, 0, 0]);
+    assert!(encoded.qoi_uses_alpha().unwrap());
+}
+
+#[test]
+fn encode_without_index() {
+    // Two alternating colours, which the default strategy encodes with INDEX.
+    let raw: Vec<u8> = (0..64)
+        .flat_map(|i| {
+            if i % 2 == 0 {
+                [200, 10, 60, 255]
+            } else {
+                [5, 90, 250, 128]
+            }
+        })
+        .collect();
+    let options = EncodeOptions {
+        strategy: EncodeStrategy::NoIndex,
+        ..Default::default()
+    };
+
+    let default = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let encoded = raw
+        .qoi_encode_to_vec_with_options(8, 8, Channels::Four, ColourSpace::Srgb, options)
+        .unwrap();
+    assert_eq!(default.qoi_stats().unwrap().index.count, 62);
+    assert_eq!(encoded.qoi_stats().unwrap().index.count, 0);
+    assert!(encoded.len() > default.len());
+    compare_bytes(&encoded.qoi_decode_to_vec(None).unwrap(), &raw);
+
+    let mut dest = vec![0; encoded.len()];
+    let len = raw
+        .qoi_encode_with_options(8, 8, Channels::Four, ColourSpace::Srgb, &mut dest, options)
+        .unwrap();
+    compare_bytes(&dest[..len], &encoded);
+}
+
+#[test]
+fn opcode_constants() {
+    let raw = [
+        10, 10, 10, 255, 11, 11, 11, 255, 10, 10, 10, 255, 10, 10, 10, 255,
+    ];
+    let encoded = raw
+        .qoi_encode_to_vec(4, 1, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let opcodes = &encoded[14..encoded.len() - 4];
+    assert_eq!(opcodes[0] & Qoi::MASK_4, Qoi::DIFF_24);
+    assert_eq!(opcodes[3] & Qoi::MASK_2, Qoi::DIFF_8);
+    assert_eq!(opcodes[4] & Qoi::MASK_2, Qoi::INDEX);
+    assert_eq!(opcodes[5] & Qoi::MASK_3, Qoi::RUN_8);
+    assert_eq!(opcodes.len(), 6);
+}
+
+#[test]
+fn index_preferred_over_diff() {
+    // The third pixel is both a DIFF_8 from the second and in the cache.
+    let raw = [10, 10, 10, 255, 11, 11, 11, 255, 10, 10, 10, 255];
+    let encoded = raw
+        .qoi_encode_to_vec(3, 1, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let stats = encoded.qoi_stats().unwrap();
+    assert_eq!(stats.diff_24.count, 1);
+    assert_eq!(stats.diff_8.count, 1);
+    assert_eq!(stats.index.count, 1);
+
+    let options = EncodeOptions {
+        strategy: EncodeStrategy::PaletteBiased,
+        ..Default::default()
+    };
+    let biased = raw
+        .qoi_encode_to_vec_with_options(3, 1, Channels::Four, ColourSpace::Srgb, options)
+        .unwrap();
+    assert_eq!(biased, encoded);
+}
+
+#[test]
+fn stats_csv() {
+    let mut encoded = header_bytes(5, 1, 4);
+    encoded.extend_from_slice(&[0x42, 0xaa, 0xf3, 1, 2, 0, 0, 0, 0]);
+
+    assert_eq!(
+        encoded.qoi_stats_csv().unwrap(),
+        "opcode,count,bytes,percent\n\
+         index,0,0,0.00\n\
+         run_8,1,1,20.00\n\
+         run_16,0,0,0.00\n\
+         diff_8,1,1,20.00\n\
+         diff_16,0,0,0.00\n\
+         diff_24,0,0,0.00\n\
+         color,1,3,60.00\n"
+    );
+    assert_eq!(
+        (&encoded[..10]).qoi_stats_csv(),
+        Err(QoiError::InputSmallerThanHeader)
+    );
+}
+
+#[test]
+fn decode_runs() {
+    let mut encoded = header_bytes(50, 1, 4);
+    // DIFF_8, a RUN_16 of 32 + 2 + 1 pixels, DIFF_8, and a RUN_8 of 20 pixels
+    // which is cut short by the end of the image.
+    encoded.extend_from_slice(&[0xbf, 0x60, 0x02, 0xaa, 0x53, 0, 0, 0, 0]);
+
+    let runs: Vec<_> = encoded.qoi_decode_runs().unwrap().collect();
+    assert_eq!(
+        runs,
+        [
+            Ok((Pixel::new(1, 1, 1, 255), 1)),
+            Ok((Pixel::new(1, 1, 1, 255), 35)),
+            Ok((Pixel::new(1, 1, 1, 255), 1)),
+            Ok((Pixel::new(1, 1, 1, 255), 13)),
+        ]
+    );
+
+    // A COLOR running into the padding is reported once, after the runs
+    // before it.
+    let mut truncated = header_bytes(50, 1, 4);
+    truncated.extend_from_slice(&[0x60, 0x02, 0xff, 1, 2, 3, 4, 0, 0]);
+    let runs: Vec<_> = truncated.qoi_decode_runs().unwrap().collect();
+    assert_eq!(
+        runs,
+        [
+            Ok((Pixel::new(0, 0, 0, 255), 35)),
+            Err(QoiError::UnexpectedEnd { pos: 16 })
+        ]
+    );
+
+    for_all_qoi_files(|case| {
+        println!("Testing {}", case.path.display());
+        let mut expanded = Vec::new();
+        for run in case.encoded.qoi_decode_runs().unwrap() {
+            let (pixel, count) = run.unwrap();
+            for _ in 0..count {
+                expanded.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+            }
+        }
+
+        let decoded = case
+            .encoded
+            .qoi_decode_to_vec(Some(Channels::Four))
+            .unwrap();
+        compare_bytes(&expanded, &decoded);
+    });
+}
+
+#[test]
+fn run_length_boundaries() {
+    fn run_ops(mut run: usize) -> Vec<u8> {
+        let mut ops = Vec::new();
+        while run > 0 {
+            let len = run.min(0x2020);
+            if len < 33 {
+                ops.push(0x40 | (len - 1) as u8);
+            } else {
+                ops.extend_from_slice(&[0x60 | ((len - 33) >> 8) as u8, (len - 33) as u8]);
+            }
+            run -= len;
+        }
+        ops
+    }
+
+    for run in [32, 33, 0x201f, 0x2020, 0x2021] {
+        // The run either ends on the final pixel, or is followed by one
+        // which is encoded as a DIFF_8.
+        for mid_image in [false, true] {
+            let mut raw = [0, 0, 0, 255].repeat(run);
+            let mut expected = header_bytes(run as u32 + mid_image as u32, 1, 4);
+            expected.extend(run_ops(run));
+
+            if mid_image {
+                raw.extend_from_slice(&[1, 1, 1, 255]);
+                expected.push(0xbf);
+            }
+            expected.extend_from_slice(&[0; 4]);
+
+            let width = raw.len() as u32 / 4;
+            let encoded = raw
+                .qoi_encode_to_vec(width, 1, Channels::Four, ColourSpace::Srgb)
+                .unwrap();
+            assert_eq!(encoded, expected, "run {run}, mid image {mid_image}");
+            assert_eq!(encoded.qoi_decode_to_vec(None).unwrap(), raw);
+        }
+    }
+}
+
+#[test]
+fn long_runs_round_trip() {
+    for channels in [Channels::Three, Channels::Four] {
+        let pixel_size = channels.count() as usize;
+        let (width, height) = (0x2100, 3);
+
+        // Runs of varying lengths which start and end at different offsets
+        // within 48 byte blocks, separated by single different pixels.
+        let mut raw = Vec::new();
+        let mut run = 1;
+        while raw.len() < width * height * pixel_size {
+            raw.extend(std::iter::repeat_n(7, run * pixel_size));
+            raw.extend(std::iter::repeat_n(9, pixel_size));
+            run = run * 3 + 1;
+        }
+        raw.truncate(width * height * pixel_size);
+
+        let encoded = raw
+            .qoi_encode_to_vec(width as u32, height as u32, channels, ColourSpace::Srgb)
+            .unwrap();
+        let decoded = encoded.qoi_decode_to_vec(None).unwrap();
+        compare_bytes(&raw, &decoded);
+    }
+}
+
+#[test]
+fn decode_with_trailing_bytes() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let mut appended = encoded.clone();
+    appended.extend_from_slice(b"metadata\xff\x00\x01");
+
+    compare_bytes(&appended.qoi_decode_to_vec(None).unwrap(), &raw);
+
+    let options = DecodeOptions {
+        strict: true,
+        ..Default::default()
+    };
+    let mut decoded = vec![0; raw.len()];
+    appended
+        .qoi_decode_with_options(None, &mut decoded, options)
+        .unwrap();
+    compare_bytes(&decoded, &raw);
+    assert_eq!(
+        appended.qoi_decode_counting(None, &mut decoded),
+        Ok(encoded.len())
+    );
+
+    // The padding is found after the last opcode rather than at the end.
+    let mut bad_padding = encoded[..encoded.len() - 1].to_vec();
+    bad_padding.extend_from_slice(&[1, 0, 0, 0, 0]);
+    assert_eq!(
+        bad_padding.qoi_decode_with_options(None, &mut decoded, options),
+        Err(QoiError::InvalidPadding)
+    );
+
+    let header = QoiHeader::new(8, 8, Channels::Four, ColourSpace::Srgb);
+    let mut standard = vec![0; header.max_encoded_size() + 4];
+    let len = raw
+        .qoi_encode_standard(8, 8, Channels::Four, ColourSpace::Srgb, &mut standard)
+        .unwrap();
+    standard.truncate(len);
+    standard.extend_from_slice(&[0xff; 6]);
+    standard.qoi_decode_standard(None, &mut decoded).unwrap();
+    compare_bytes(&decoded, &raw);
+}
+
+#[test]
+fn opcode_cursor() {
+    let mut encoded = header_bytes(40, 1, 4);
+    encoded.extend_from_slice(&[
+        0xbf, 0x42, 0xd5, 0x5a, 0xf9, 10, 128, 0xe0, 0x7e, 0x0f, 0x60, 0x00, 0, 0, 0, 0,
+    ]);
+
+    let mut cursor = OpcodeCursor::new(&encoded).unwrap();
+    let mut steps = Vec::new();
+    while let Some(step) = cursor.next_op().unwrap() {
+        steps.push((step, cursor.pos()));
+    }
+
+    assert_eq!(
+        steps,
+        [
+            (
+                (
+                    Opcode::Diff8 {
+                        dr: 1,
+                        dg: 1,
+                        db: 1
+                    },
+                    Pixel::new(1, 1, 1, 255)
+                ),
+                15
+            ),
+            ((Opcode::Run(3), Pixel::new(1, 1, 1, 255)), 16),
+            (
+                (
+                    Opcode::Diff16 {
+                        dr: 5,
+                        dg: -3,
+                        db: 2
+                    },
+                    Pixel::new(6, 254, 3, 255)
+                ),
+                18
+            ),
+            (
+                (
+                    Opcode::Color {
+                        r: Some(10),
+                        g: None,
+                        b: None,
+                        a: Some(128)
+                    },
+                    Pixel::new(10, 254, 3, 128)
+                ),
+                21
+            ),
+            (
+                (
+                    Opcode::Diff24 {
+                        dr: -16,
+                        dg: 15,
+                        db: 0,
+                        da: -1
+                    },
+                    Pixel::new(250, 13, 3, 127)
+                ),
+                24
+            ),
+            ((Opcode::Run(33), Pixel::new(250, 13, 3, 127)), 26),
+        ]
+    );
+
+    for_all_qoi_files(|case| {
+        let header = case.encoded.load_qoi_header().unwrap();
+        let mut cursor = OpcodeCursor::new(&case.encoded).unwrap();
+        let mut pixels = Vec::new();
+
+        while let Some((opcode, pixel)) = cursor.next_op().unwrap() {
+            pixels.extend(std::iter::repeat_n(pixel, opcode.pixels()));
+        }
+        pixels.truncate((header.width() * header.height()) as usize);
+
+        let expected: Vec<_> = case
+            .encoded
+            .qoi_pixels()
+            .unwrap()
+            .map(|pixel| pixel.map(|(r, g, b, a)| Pixel::new(r, g, b, a)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(pixels, expected, "{}", case.path.display());
+    });
+}
+
+#[test]
+fn identical_pixels_encode_as_runs() {
+    // The encoder starts with this pixel as the previous one.
+    let raw = [0, 0, 0, 255].repeat(100 * 100);
+    let encoded = raw
+        .qoi_encode_to_vec(100, 100, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let stats = encoded.qoi_stats().unwrap();
+    assert_eq!(stats.run_8.count + stats.run_16.count, stats.opcodes());
+    assert_eq!(stats.color, OpcodeStats::default());
+
+    let mut decoded = vec![0; raw.len()];
+    encoded.qoi_decode(None, &mut decoded).unwrap();
+    compare_bytes(&decoded, &raw);
+}
+
+#[test]
+fn opcode_straddling_padding() {
+    // A RUN_16 whose second byte would be read from the padding.
+    let mut encoded = header_bytes(10, 10, 4);
+    encoded.extend_from_slice(&[0x60, 0, 0, 0, 0]);
+
+    let mut decoded = vec![0; 400];
+    let error = encoded.qoi_decode(None, &mut decoded).unwrap_err();
+    assert_eq!(error, QoiError::UnexpectedEnd { pos: 14 });
+    assert_eq!(
+        error.to_string(),
+        "The input ended at byte 14 before every pixel was decoded"
+    );
+    assert_eq!(encoded.qoi_validate().unwrap_err(), error);
+    assert_eq!(encoded.qoi_stats().unwrap_err(), error);
+
+    // A COLOR command whose channels would be read from the padding.
+    let mut encoded = header_bytes(1, 1, 4);
+    encoded.extend_from_slice(&[0xff, 1, 2, 0, 0, 0, 0]);
+    let error = encoded.qoi_decode(None, &mut decoded).unwrap_err();
+    assert_eq!(error, QoiError::UnexpectedEnd { pos: 14 });
+}
+
+#[test]
+fn colour_space_byte() {
+    let mut encoded = [0u8; 12]
+        .qoi_encode_to_vec(2, 2, Channels::Three, ColourSpace::Linear)
+        .unwrap();
+    assert_eq!(encoded[13], 0x0f);
+    assert_eq!(
+        encoded.load_qoi_header().unwrap().colour_space(),
+        ColourSpace::Linear
+    );
+
+    encoded[13] = 2;
+    assert!(matches!(
+        encoded.load_qoi_header().unwrap_err(),
+        QoiError::InvalidHeader
+    ));
+}
+
+#[test]
+fn pixels_iterator() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let pixels: Vec<u8> = encoded
+        .qoi_pixels()
+        .unwrap()
+        .flat_map(|pixel| {
+            let (r, g, b, a) = pixel.unwrap();
+            [r, g, b, a]
+        })
+        .collect();
+    compare_bytes(&pixels, &raw);
+
+    // Runs are expanded.
+    let encoded = [7u8; 300]
+        .qoi_encode_to_vec(10, 10, Channels::Three, ColourSpace::Srgb)
+        .unwrap();
+    let pixels = encoded.qoi_pixels().unwrap();
+    assert_eq!(pixels.size_hint(), (1, Some(100)));
+    assert!(pixels.eq(std::iter::repeat_n(Ok((7, 7, 7, 255)), 100)));
+
+    // A COLOR running into the padding is reported once, after the pixels
+    // before it.
+    let mut truncated = header_bytes(2, 2, 4);
+    truncated.extend_from_slice(&[0xbf, 0xff, 1, 2, 3, 4, 0, 0]);
+    let pixels: Vec<_> = truncated.qoi_pixels().unwrap().collect();
+    assert_eq!(
+        pixels,
+        [Ok((1, 1, 1, 255)), Err(QoiError::UnexpectedEnd { pos: 15 })]
+    );
+}
+
+#[test]
+fn empty_image() {
+    for (width, height) in [(0, 4), (4, 0), (0, 0)] {
+        let error = [0u8; 64]
+            .qoi_encode_to_vec(width, height, Channels::Four, ColourSpace::Srgb)
+            .unwrap_err();
+        assert!(matches!(error, QoiError::EmptyImage));
+
+        let error = [0u8; 64]
+            .qoi_encode(width, height, Channels::Four, ColourSpace::Srgb, [0; 64])
+            .unwrap_err();
+        assert!(matches!(error, QoiError::EmptyImage));
+
+        let mut encoded = header_bytes(width, height, 4);
+        encoded.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            encoded.load_qoi_header().unwrap_err(),
+            QoiError::EmptyImage
+        ));
+        assert!(matches!(
+            encoded.qoi_decode_to_vec(None).unwrap_err(),
+            QoiError::EmptyImage
+        ));
+    }
+
+    let mut encoder = QoiStreamEncoder::new(Vec::new());
+    let error = encoder
+        .write_header(0, 1, Channels::Three, ColourSpace::Srgb)
+        .unwrap_err();
+    assert!(matches!(error, QoiError::EmptyImage));
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn dynamic_image_round_trip() {
+    use image::{DynamicImage, GrayImage, RgbaImage};
+    use qoi::EncodedImage;
+
+    let raw = rgba_test_image();
+    let image = DynamicImage::ImageRgba8(RgbaImage::from_raw(8, 8, raw.clone()).unwrap());
+    let encoded = EncodedImage::try_from(&image).unwrap();
+    assert_eq!(
+        encoded.load_qoi_header().unwrap().channels(),
+        Channels::Four
+    );
+
+    let decoded = encoded.decode_to_dynamic_image().unwrap();
+    assert_eq!(decoded, image);
+
+    let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+    let encoded = EncodedImage::try_from(rgb.clone()).unwrap();
+    assert_eq!(encoded.decode_to_dynamic_image().unwrap(), rgb);
+
+    let gray = DynamicImage::ImageLuma8(GrayImage::new(2, 2));
+    assert!(matches!(
+        EncodedImage::try_from(gray).unwrap_err(),
+        QoiError::UnsupportedFormat
+    ));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_header() {
+    let header = QoiHeader::new(640, 480, Channels::Four, ColourSpace::Linear);
+    let json = serde_json::to_string(&header).unwrap();
+    assert_eq!(
+        json,
+        r#"{"width":640,"height":480,"channels":4,"colour_space":15}"#
+    );
+
+    let header: QoiHeader = serde_json::from_str(&json).unwrap();
+    assert_eq!(header.width(), 640);
+    assert_eq!(header.height(), 480);
+    assert_eq!(header.channels(), Channels::Four);
+    assert_eq!(header.colour_space(), ColourSpace::Linear);
+
+    assert!(serde_json::from_str::<Channels>("5").is_err());
+}
+
+#[cfg(feature = "verify")]
+#[test]
+fn verifying_reader() {
+    use qoi::VerifyingReader;
+
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let mut decoded = vec![0; raw.len()];
+
+    let mut reader = VerifyingReader::new(&encoded);
+    assert_eq!(reader.digest(), None);
+    reader.decode(None, &mut decoded).unwrap();
+    let digest = reader.digest().unwrap();
+    compare_bytes(&decoded, &raw);
+
+    // The digest only depends on the decoded pixels.
+    let mut other = encoded.clone();
+    other.extend_from_slice(b"trailing");
+    let mut reader = VerifyingReader::new(&other);
+    reader.decode(None, &mut decoded).unwrap();
+    assert_eq!(reader.digest(), Some(digest));
+
+    let mut changed = raw.clone();
+    changed[0] ^= 1;
+    let changed = changed
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let mut reader = VerifyingReader::new(&changed);
+    reader.decode(None, &mut decoded).unwrap();
+    assert_ne!(reader.digest(), Some(digest));
+
+    assert!(reader.decode(None, &mut decoded[1..]).is_err());
+    assert_eq!(reader.digest(), None);
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn arbitrary_header() {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    let data = [0xff; 16];
+    let header = QoiHeader::arbitrary(&mut Unstructured::new(&data)).unwrap();
+    assert_eq!(header.width(), u32::MAX);
+    assert_eq!(header.height(), u32::MAX);
+    assert!(!header.fits());
+
+    let mut channels = Vec::new();
+    let mut colour_spaces = Vec::new();
+    for byte in 0..=255u8 {
+        let data = [byte; 4];
+        channels.push(Channels::arbitrary(&mut Unstructured::new(&data)).unwrap());
+        colour_spaces.push(ColourSpace::arbitrary(&mut Unstructured::new(&data)).unwrap());
+    }
+
+    assert!(channels.contains(&Channels::Three) && channels.contains(&Channels::Four));
+    for colour_space in [
+        ColourSpace::Srgb,
+        ColourSpace::SrgbLinearAlpha,
+        ColourSpace::Linear,
+    ] {
+        assert!(colour_spaces.contains(&colour_space));
+    }
+}
+
+#[test]
+fn encode_with_cache() {
+    let first = rgba_test_image();
+    let mut second = first.clone();
+    second[..8].copy_from_slice(&[1, 2, 3, 4, 1, 2, 3, 4]);
+    let frames = [first, second];
+
+    let mut cache = [Pixel::default(); 64];
+    let mut previous = Pixel::new(0, 0, 0, 255);
+    let mut encoded = Vec::new();
+
+    for frame in &frames {
+        let mut dest = vec![0; 1024];
+        let len = frame
+            .qoi_encode_with_cache(
+                8,
+                8,
+                Channels::Four,
+                ColourSpace::Srgb,
+                &mut cache,
+                &mut previous,
+                &mut dest,
+            )
+            .unwrap();
+        dest.truncate(len);
+        encoded.push(dest);
+    }
+
+    let standalone = frames[1]
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert!(encoded[1].len() < standalone.len());
+
+    let mut cache = [Pixel::default(); 64];
+    let mut previous = Pixel::new(0, 0, 0, 255);
+    for (frame, encoded) in frames.iter().zip(&encoded) {
+        let mut decoded = vec![0; frame.len()];
+        encoded
+            .qoi_decode_with_cache(None, &mut cache, &mut previous, &mut decoded)
+            .unwrap();
+        compare_bytes(&decoded, frame);
+    }
+
+    // A seeded cache which doesn't hold the previous pixel, with an image
+    // starting with a run of it.
+    let raw = [[9, 9, 9, 9], [9, 9, 9, 9], [1, 1, 1, 1], [9, 9, 9, 9]].concat();
+    let seed = [Pixel::new(1, 1, 1, 1); 64];
+    let mut cache = seed;
+    let mut previous = Pixel::new(9, 9, 9, 9);
+    let mut encoded = vec![0; 64];
+    let len = raw
+        .qoi_encode_with_cache(
+            4,
+            1,
+            Channels::Four,
+            ColourSpace::Srgb,
+            &mut cache,
+            &mut previous,
+            &mut encoded,
+        )
+        .unwrap();
+
+    let mut cache = seed;
+    let mut previous = Pixel::new(9, 9, 9, 9);
+    let mut decoded = [0; 16];
+    (&encoded[..len])
+        .qoi_decode_with_cache(None, &mut cache, &mut previous, &mut decoded)
+        .unwrap();
+    assert_eq!(decoded, raw.as_slice());
+}
+
+#[test]
+fn decode_counting_concatenated() {
+    let first = rgba_test_image();
+    let second: Vec<u8> = first.iter().rev().copied().collect();
+    let first_encoded = first
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let second_encoded = second
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let mut container = first_encoded.clone();
+    container.extend_from_slice(&second_encoded);
+
+    let mut decoded = vec![0; first.len()];
+    let consumed = container.qoi_decode_counting(None, &mut decoded).unwrap();
+    assert_eq!(consumed, first_encoded.len());
+    compare_bytes(&decoded, &first);
+
+    let consumed = (&container[consumed..])
+        .qoi_decode_counting(None, &mut decoded)
+        .unwrap();
+    assert_eq!(consumed, second_encoded.len());
+    compare_bytes(&decoded, &second);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn parallel_round_trip() {
+    let raw: Vec<u8> = (0..37 * 23 * 4).map(|i| (i / 7 % 251) as u8).collect();
+    let encoded = raw
+        .qoi_encode_parallel(37, 23, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    assert_eq!(&encoded[..4], b"qoip");
+
+    let (header, decoded) = encoded.qoi_decode_parallel(None).unwrap();
+    assert_eq!((header.width(), header.height()), (37, 23));
+    compare_bytes(&decoded, &raw);
+
+    let (_, decoded) = encoded.qoi_decode_parallel(Some(Channels::Three)).unwrap();
+    let expected: Vec<u8> = raw
+        .chunks_exact(4)
+        .flat_map(|p| [p[0], p[1], p[2]])
+        .collect();
+    compare_bytes(&decoded, &expected);
+
+    assert!(matches!(
+        (&encoded[..20]).qoi_decode_parallel(None).unwrap_err(),
+        QoiError::InvalidHeader
+    ));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn validate_all() {
+    let dir = std::env::temp_dir().join(format!("qoi-validate-all-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let encoded = rgba_test_image()
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+    let valid = dir.join("valid.qoi");
+    std::fs::write(&valid, &encoded).unwrap();
+    let truncated = dir.join("truncated.qoi");
+    std::fs::write(&truncated, &encoded[..10]).unwrap();
+    let missing = dir.join("missing.qoi");
+
+    let paths = vec![valid, truncated, missing];
+    let results = qoi::qoi_validate_all(&paths);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let returned: Vec<_> = results.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(returned, paths);
+    assert_eq!(
+        results[0].1,
+        Ok(QoiHeader::new(8, 8, Channels::Four, ColourSpace::Srgb))
+    );
+    assert_eq!(results[1].1, Err(QoiError::InputSmallerThanHeader));
+    assert!(matches!(
+        &results[2].1,
+        Err(QoiError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound
+    ));
+}
+
+#[test]
+fn decode_into_mmap() {
+    let raw = rgba_test_image();
+    let encoded = raw
+        .qoi_encode_to_vec(8, 8, Channels::Four, ColourSpace::Srgb)
+        .unwrap();
+
+    let path = std::env::temp_dir().join(format!("qoi-mmap-{}.raw", std::process::id()));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+
+    file.set_len(raw.len() as u64).unwrap();
+    let mut map = unsafe { memmap2::MmapMut::map_mut(&file).unwrap() };
+    encoded.qoi_decode(None, &mut map[..]).unwrap();
+    map.flush().unwrap();
+    drop(map);
+    compare_bytes(&std::fs::read(&path).unwrap(), &raw);
+
+    // A short map is reported instead of writing past its end.
+    file.set_len(raw.len() as u64 - 1).unwrap();
+    let mut map = unsafe { memmap2::MmapMut::map_mut(&file).unwrap() };
+    let error = encoded.qoi_decode(None, &mut map[..]).unwrap_err();
+    assert_eq!(
+        error,
+        QoiError::OutputTooSmall {
+            needed: raw.len(),
+            got: raw.len() - 1
+        }
+    );
+
+    drop(map);
+    drop(file);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn ffi_round_trip() {
+    use qoi::ffi::{qoi_decode_raw, qoi_encode_raw, qoi_free, QoiDesc};
+
+    let raw = rgba_test_image();
+    let mut error = -1;
+    let mut len = 0;
+    let encoded = unsafe { qoi_encode_raw(raw.as_ptr(), 8, 8, 4, &mut len, &mut error) };
+    assert!(!encoded.is_null());
+    assert_eq!(error, 0);
+
+    let mut desc = QoiDesc::default();
+    let decoded = unsafe { qoi_decode_raw(encoded, len, 0, &mut desc, &mut error) };
+    assert!(!decoded.is_null());
+    assert_eq!(
+        desc,
+        QoiDesc {
+            width: 8,
+            height: 8,
+            channels: 4,
+            colorspace: 0,
+        }
+    );
+    compare_bytes(
+        unsafe { std::slice::from_raw_parts(decoded, raw.len()) },
+        &raw,
+    );
+
+    let decoded_3 = unsafe { qoi_decode_raw(encoded, len, 5, &mut desc, &mut error) };
+    assert!(decoded_3.is_null());
+    assert_eq!(error, 3);
+
+    unsafe {
+        qoi_free(decoded, raw.len());
+        qoi_free(encoded, len);
+    }
+
+    let encoded = unsafe { qoi_encode_raw(raw.as_ptr(), 0, 8, 4, &mut len, &mut error) };
+    assert!(encoded.is_null());
+    assert_eq!(error, 13);
+}
+
+#[test]
+fn invalid_header_channels() {
+    for channels in (0..=u8::MAX).filter(|c| *c != 3 && *c != 4) {
+        // The dimensions would be rejected as too big if they were used to
+        // size the output before the channels were checked.
+        let mut input = header_bytes(u32::MAX, u32::MAX, channels);
+        input.extend_from_slice(&[0; 4]);
+
+        assert_eq!(input.load_qoi_header().unwrap_err(), QoiError::Channels);
+        assert_eq!(
+            input.qoi_decode_to_vec(None).unwrap_err(),
+            QoiError::Channels
+        );
+        assert_eq!(
+            input.qoi_decode_to_vec(Some(Channels::Four)).unwrap_err(),
+            QoiError::Channels
+        );
+    }
+}
+
+#[test]
+fn errors_compare() {
+    let cases = vec![
+        (b"qoif".to_vec(), QoiError::InputSmallerThanHeader),
+        (b"boif1234123412".to_vec(), QoiError::IncorrectHeaderMagic),
+        (header_bytes(1, 1, 5), QoiError::Channels),
+        (header_bytes(0, 1, 4), QoiError::EmptyImage),
+    ];
+
+    for (input, expected) in &cases {
+        let error = input.load_qoi_header().unwrap_err();
+        assert_eq!(&error, expected);
+        assert_eq!(error.clone(), error);
+    }
+
+    let io = |kind| QoiError::from(std::io::Error::new(kind, "test"));
+    assert_eq!(
+        io(std::io::ErrorKind::UnexpectedEof),
+        io(std::io::ErrorKind::UnexpectedEof)
+    );
+    assert_ne!(
+        io(std::io::ErrorKind::UnexpectedEof),
+        io(std::io::ErrorKind::Other)
+    );
+    assert_ne!(io(std::io::ErrorKind::Other), QoiError::InputSize);
+}
+
+#[test]
+fn error_source() {
+    use std::error::Error;
+
+    fn assert_send_sync<T: Error + Send + Sync + 'static>() {}
+    assert_send_sync::<QoiError>();
+
+    let error = QoiError::from(std::io::Error::other("test"));
+    let source = error.source().unwrap();
+    let inner = source.downcast_ref::<std::io::Error>().unwrap();
+    assert_eq!(inner.kind(), std::io::ErrorKind::Other);
+    assert_eq!(inner.to_string(), "test");
+
+    assert!(QoiError::InputSize.source().is_none());
+
+    // Boxing keeps the cause reachable.
+    let boxed: Box<dyn Error + Send + Sync> = Box::new(error);
+    assert!(boxed.source().unwrap().is::<std::io::Error>());
 }